@@ -0,0 +1,264 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use std::time::Duration;
+
+use crate::field::*;
+use crate::grid::*;
+use crate::rules::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AttackPreset {
+    // the guideline table Tetris 99 plays with
+    Guideline,
+    // multiplies attacks by the combo and grows the back to back bonus with the chain
+    Tetrio,
+    PuyoPuyoTetris,
+}
+impl AttackPreset {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "guideline" | "t99" => Some(Self::Guideline),
+            "tetrio" => Some(Self::Tetrio),
+            "ppt" => Some(Self::PuyoPuyoTetris),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TSpin {
+    None,
+    // only one of the two corners in front of the T is filled
+    Mini,
+    Full,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Combo {
+    // extra lines for every clear after the first one in a row, the last entry repeats
+    Table(&'static [u32]),
+    // the attack grows by this fraction for every clear after the first one,
+    // clears that send nothing get a logarithmic bonus instead
+    Multiplier(f32),
+}
+
+// the clear a placement made, as far as attacking cares
+pub struct Clear {
+    pub lines: u32,
+    pub t_spin: TSpin,
+    // clears in a row including this one
+    pub combo: u32,
+    // tetrises and t-spins in a row before this one
+    pub back_to_back: u32,
+    pub perfect_clear: bool,
+}
+impl Clear {
+    // a clear that keeps the back to back chain going
+    pub fn is_difficult(&self) -> bool {
+        self.lines >= 4 || (self.lines > 0 && self.t_spin != TSpin::None)
+    }
+}
+
+// how many lines every clear sends and how the garbage arrives on the other side
+#[derive(Resource, Debug, Clone)]
+pub struct AttackTable {
+    // lines sent for clearing 0 to 4 lines at once, normally and with a t-spin
+    pub lines: [u32; 5],
+    pub t_spin: [u32; 4],
+    pub t_spin_mini: [u32; 3],
+    pub combo: Combo,
+    // the bonus for a chain at least as long as the first value, the longest matching chain counts
+    pub back_to_back: &'static [(u32, u32)],
+    pub perfect_clear: u32,
+    // the chance in percent that the hole moves between two rows of the same attack
+    pub messiness: u32,
+    // how long garbage waits before it can rise
+    pub delay: Duration,
+    // the most rows that rise after a single placement, the rest keeps waiting
+    pub cap: u32,
+}
+impl AttackTable {
+    pub fn new(preset: AttackPreset) -> Self {
+        match preset {
+            AttackPreset::Guideline => Self::guideline(),
+            AttackPreset::Tetrio => Self::tetrio(),
+            AttackPreset::PuyoPuyoTetris => Self::puyo_puyo_tetris(),
+        }
+    }
+    pub fn guideline() -> Self {
+        Self {
+            lines: [0, 0, 1, 2, 4],
+            t_spin: [0, 2, 4, 6],
+            t_spin_mini: [0, 0, 1],
+            combo: Combo::Table(&[0, 1, 1, 2, 2, 3, 3, 4, 4, 4, 5]),
+            back_to_back: &[(1, 1)],
+            perfect_clear: 10,
+            messiness: 0,
+            delay: Duration::ZERO,
+            cap: GRID_HEIGHT,
+        }
+    }
+    pub fn tetrio() -> Self {
+        Self {
+            combo: Combo::Multiplier(0.25),
+            back_to_back: &[(1, 1), (3, 2), (8, 3), (24, 4), (67, 5), (185, 6), (504, 7)],
+            delay: Duration::from_millis(333),
+            cap: 8,
+            ..Self::guideline()
+        }
+    }
+    pub fn puyo_puyo_tetris() -> Self {
+        Self {
+            combo: Combo::Table(&[0, 0, 1, 1, 1, 2, 2, 3, 3, 4, 4, 4, 5]),
+            messiness: 30,
+            ..Self::guideline()
+        }
+    }
+
+    // lines a clear sends before cancelling incoming garbage
+    pub fn attack(&self, clear: &Clear) -> u32 {
+        if clear.lines == 0 {
+            return 0;
+        }
+        let mut attack = match clear.t_spin {
+            TSpin::None => self.lines[clear.lines.min(4) as usize],
+            TSpin::Mini => self.t_spin_mini[clear.lines.min(2) as usize],
+            TSpin::Full => self.t_spin[clear.lines.min(3) as usize],
+        };
+        if clear.is_difficult() {
+            attack += self
+                .back_to_back
+                .iter()
+                .rev()
+                .find(|&&(chain, _)| clear.back_to_back >= chain)
+                .map_or(0, |&(_, bonus)| bonus);
+        }
+
+        let combo = clear.combo.max(1) - 1;
+        attack = match self.combo {
+            Combo::Table(table) => attack + table[(combo as usize).min(table.len() - 1)],
+            Combo::Multiplier(multiplier) if attack > 0 => {
+                (attack as f32 * (1.0 + multiplier * combo as f32)) as u32
+            }
+            Combo::Multiplier(multiplier) => (1.0 + multiplier * 5.0 * combo as f32).ln() as u32,
+        };
+
+        if clear.perfect_clear {
+            attack += self.perfect_clear;
+        }
+        attack
+    }
+}
+
+// three of the corners around the center of a T are filled, counting the walls and the floor,
+// and it is a full t-spin when both corners on the side the T points to are
+pub fn t_spin(grid: &Grid, spot: &Spot, rules: &Rules) -> TSpin {
+    let piece = &rules.pieces[spot.index];
+    if piece.name != 'T' || piece.cells.len() != 4 {
+        return TSpin::None;
+    }
+    let cells = spot.cells(rules);
+    let touches =
+        |(ax, ay): (i32, i32), (bx, by): (i32, i32)| (ax - bx).abs() + (ay - by).abs() == 1;
+    // the center block touches the other three, the block that points forward has none across
+    let Some(&(x, y)) = cells
+        .iter()
+        .find(|&&cell| cells.iter().filter(|&&other| touches(cell, other)).count() == 3)
+    else {
+        return TSpin::None;
+    };
+    let Some((dx, dy)) = cells
+        .iter()
+        .map(|&(cx, cy)| (cx - x, cy - y))
+        .find(|&(dx, dy)| dx.abs() + dy.abs() == 1 && !cells.contains(&(x - dx, y - dy)))
+    else {
+        return TSpin::None;
+    };
+    let filled = |(cx, cy): (i32, i32)| grid.is_filled(x + cx, y + cy) as u32;
+
+    let front = filled((dx + dy, dy - dx)) + filled((dx - dy, dy + dx));
+    let back = filled((-dx + dy, -dy - dx)) + filled((-dx - dy, -dy + dx));
+    match (front, back) {
+        (2, 1..) => TSpin::Full,
+        (1, 2) => TSpin::Mini,
+        _ => TSpin::None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clear(lines: u32, t_spin: TSpin, combo: u32, back_to_back: u32) -> Clear {
+        Clear {
+            lines,
+            t_spin,
+            combo,
+            back_to_back,
+            perfect_clear: false,
+        }
+    }
+
+    #[test]
+    fn from_name() {
+        assert_eq!(
+            AttackPreset::from_name("t99"),
+            Some(AttackPreset::Guideline)
+        );
+        assert_eq!(
+            AttackPreset::from_name("tetrio"),
+            Some(AttackPreset::Tetrio)
+        );
+        assert_eq!(
+            AttackPreset::from_name("ppt"),
+            Some(AttackPreset::PuyoPuyoTetris)
+        );
+        assert_eq!(AttackPreset::from_name("tetris"), None);
+    }
+
+    #[test]
+    fn guideline() {
+        let table = AttackTable::new(AttackPreset::Guideline);
+        assert_eq!(table.attack(&clear(0, TSpin::Full, 0, 0)), 0);
+        assert_eq!(table.attack(&clear(1, TSpin::None, 1, 0)), 0);
+        assert_eq!(table.attack(&clear(2, TSpin::None, 1, 0)), 1);
+        assert_eq!(table.attack(&clear(4, TSpin::None, 1, 0)), 4);
+        assert_eq!(table.attack(&clear(4, TSpin::None, 1, 1)), 5);
+        assert_eq!(table.attack(&clear(2, TSpin::Full, 1, 0)), 4);
+        assert_eq!(table.attack(&clear(3, TSpin::Full, 1, 5)), 7);
+        assert_eq!(table.attack(&clear(1, TSpin::Mini, 1, 0)), 0);
+        assert_eq!(table.attack(&clear(2, TSpin::Mini, 1, 1)), 2);
+        // the combo bonus, with the last entry repeating
+        assert_eq!(table.attack(&clear(1, TSpin::None, 4, 0)), 2);
+        assert_eq!(table.attack(&clear(1, TSpin::None, 30, 0)), 5);
+        let mut perfect_clear = clear(4, TSpin::None, 1, 0);
+        perfect_clear.perfect_clear = true;
+        assert_eq!(table.attack(&perfect_clear), 14);
+    }
+
+    #[test]
+    fn tetrio() {
+        let table = AttackTable::new(AttackPreset::Tetrio);
+        assert_eq!(table.attack(&clear(4, TSpin::None, 1, 0)), 4);
+        // the back to back bonus grows with the chain
+        assert_eq!(table.attack(&clear(4, TSpin::None, 1, 2)), 5);
+        assert_eq!(table.attack(&clear(4, TSpin::None, 1, 3)), 6);
+        assert_eq!(table.attack(&clear(4, TSpin::None, 1, 100)), 9);
+        // attacks are multiplied by the combo, clears that send nothing get a little
+        assert_eq!(table.attack(&clear(4, TSpin::None, 3, 0)), 6);
+        assert_eq!(table.attack(&clear(1, TSpin::None, 1, 0)), 0);
+        assert_eq!(table.attack(&clear(1, TSpin::None, 3, 0)), 1);
+        assert_eq!(table.attack(&clear(1, TSpin::None, 10, 0)), 2);
+        assert_eq!(table.cap, 8);
+    }
+
+    #[test]
+    fn puyo_puyo_tetris() {
+        let table = AttackTable::new(AttackPreset::PuyoPuyoTetris);
+        assert_eq!(table.attack(&clear(1, TSpin::None, 2, 0)), 0);
+        assert_eq!(table.attack(&clear(1, TSpin::None, 3, 0)), 1);
+        assert_eq!(table.attack(&clear(4, TSpin::None, 6, 1)), 7);
+        assert_eq!(table.messiness, 30);
+    }
+}
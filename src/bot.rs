@@ -0,0 +1,276 @@
+use bevy::prelude::*;
+
+use crate::controls::*;
+use crate::field::*;
+use crate::grid::*;
+use crate::mode::*;
+use crate::rules::*;
+use crate::*;
+
+pub const BOT_KEY: KeyCode = KeyCode::KeyB;
+
+// how much every feature of a board is worth, the bot places where the sum is the highest
+const HEIGHT_WEIGHT: f32 = -0.51;
+const LINES_WEIGHT: f32 = 0.76;
+const HOLES_WEIGHT: f32 = -0.36;
+const BUMPINESS_WEIGHT: f32 = -0.18;
+const WELLS_WEIGHT: f32 = -0.1;
+const T_SLOTS_WEIGHT: f32 = 0.3;
+
+fn column_heights(grid: &Grid) -> Vec<u32> {
+    (0..grid.width)
+        .map(|x| {
+            grid.rows
+                .iter()
+                .rposition(|row| row & (1 << x) != 0)
+                .map_or(0, |y| y as u32 + 1)
+        })
+        .collect()
+}
+
+// empty cells with a filled cell somewhere above them
+fn holes(grid: &Grid, heights: &[u32]) -> u32 {
+    (0..grid.width as i32)
+        .map(|x| {
+            (0..heights[x as usize] as i32)
+                .filter(|&y| !grid.is_filled(x, y))
+                .count() as u32
+        })
+        .sum()
+}
+
+// the depth of every well except the deepest one, a single well is kept for clearing lines
+fn wells(heights: &[u32]) -> u32 {
+    let mut depths: Vec<u32> = (0..heights.len())
+        .map(|x| {
+            let left = x.checked_sub(1).map_or(u32::MAX, |x| heights[x]);
+            let right = heights.get(x + 1).copied().unwrap_or(u32::MAX);
+            left.min(right).saturating_sub(heights[x])
+        })
+        .collect();
+    depths.sort();
+    depths.pop();
+    depths.iter().sum()
+}
+
+// spots a T can spin into, three of the corners around the center are filled
+fn t_slots(grid: &Grid) -> u32 {
+    let mut slots = 0;
+    for y in 1..grid.height() as i32 {
+        for x in 1..grid.width as i32 - 1 {
+            let open = [(x, y), (x - 1, y), (x + 1, y), (x, y - 1)]
+                .iter()
+                .all(|&(x, y)| !grid.is_filled(x, y));
+            let corners = [
+                (x - 1, y - 1),
+                (x + 1, y - 1),
+                (x - 1, y + 1),
+                (x + 1, y + 1),
+            ]
+            .iter()
+            .filter(|&&(x, y)| grid.is_filled(x, y))
+            .count();
+            if open && corners >= 3 {
+                slots += 1;
+            }
+        }
+    }
+    slots
+}
+
+pub fn evaluate(grid: &Grid, lines: u32) -> f32 {
+    let heights = column_heights(grid);
+    let height: u32 = heights.iter().sum();
+    let bumpiness: u32 = heights.windows(2).map(|w| w[0].abs_diff(w[1])).sum();
+
+    height as f32 * HEIGHT_WEIGHT
+        + lines as f32 * LINES_WEIGHT
+        + holes(grid, &heights) as f32 * HOLES_WEIGHT
+        + bumpiness as f32 * BUMPINESS_WEIGHT
+        + wells(&heights) as f32 * WELLS_WEIGHT
+        + t_slots(grid) as f32 * T_SLOTS_WEIGHT
+}
+
+// moves the active tetris along a path through the same keys the player of the board would press
+#[derive(Default)]
+pub struct Controller {
+    // the spots from the active tetris to where it gets placed
+    pub path: Vec<Spot>,
+    pub pressed: Option<KeyCode>,
+}
+impl Controller {
+    pub fn release(&mut self, keys: &mut ButtonInput<KeyCode>) {
+        if let Some(key) = self.pressed.take() {
+            keys.release(key);
+        }
+    }
+    // taps are released on the next frame so the key can be pressed again,
+    // false when this frame is spent on that
+    pub fn release_tap(&mut self, keys: &mut ButtonInput<KeyCode>, controls: &Controls) -> bool {
+        match self.pressed {
+            Some(key) if key == controls.soft_drop => true,
+            None => true,
+            Some(_) => {
+                self.release(keys);
+                false
+            }
+        }
+    }
+    pub fn press(&mut self, keys: &mut ButtonInput<KeyCode>, key: KeyCode) {
+        if self.pressed == Some(key) {
+            return; // still soft dropping
+        }
+        self.release(keys);
+        keys.press(key);
+        self.pressed = Some(key);
+    }
+    // the path has to contain the current spot
+    pub fn follow(
+        &mut self,
+        keys: &mut ButtonInput<KeyCode>,
+        current: Spot,
+        rules: &Rules,
+        controls: &Controls,
+    ) {
+        let action = next_action(&self.path, current, rules);
+        self.press(keys, controls.key(action));
+    }
+}
+
+// the action that takes the tetris from the current spot to the next one on the path
+pub fn next_action(path: &[Spot], current: Spot, rules: &Rules) -> Action {
+    let index = path.iter().position(|&spot| spot == current).unwrap();
+    match path.get(index + 1) {
+        None if rules.hard_drop => Action::HardDrop,
+        None => Action::SoftDrop,
+        Some(next) if next.orientation != current.orientation => Action::Rotate,
+        Some(next) if next.x < current.x => Action::Left,
+        Some(next) if next.x > current.x => Action::Right,
+        Some(_) => Action::SoftDrop,
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct Bot {
+    pub enabled: bool,
+    pub controller: Controller,
+    // the placed tetris count and hold the path was found for
+    pub key: Option<(u32, Option<usize>)>,
+}
+
+pub fn setup(mut commands: Commands) {
+    commands.insert_resource(Bot::default());
+}
+
+// the best spot for a tetris that was just spawned
+pub fn best_spot(grid: &Grid, index: usize, rules: &Rules) -> Option<Spot> {
+    grid.placements(index, rules)
+        .into_iter()
+        .map(|spot| {
+            let mut grid = grid.clone();
+            let lines = grid.place(&spot, rules);
+            (evaluate(&grid, lines), spot)
+        })
+        .max_by(|(a, _), (b, _)| a.total_cmp(b))
+        .map(|(_, spot)| spot)
+}
+
+// the best spot for the active tetris and whether holding first leads to a better one
+pub fn plan(
+    grid: &Grid,
+    current: Spot,
+    hold: Option<Spot>,
+    rules: &Rules,
+) -> Option<(Vec<Spot>, bool)> {
+    let score = |spot: &Spot| {
+        let mut grid = grid.clone();
+        let lines = grid.place(spot, rules);
+        evaluate(&grid, lines)
+    };
+    let best = grid
+        .reach(current, rules)
+        .into_iter()
+        .map(|(spot, path)| (score(&spot), path))
+        .max_by(|(a, _), (b, _)| a.total_cmp(b));
+    let best_hold = hold
+        .map(|spawn| grid.placements(spawn.index, rules))
+        .unwrap_or_default()
+        .iter()
+        .map(score)
+        .max_by(f32::total_cmp);
+
+    match (best, best_hold) {
+        (Some((score, _)), Some(hold_score)) if hold_score > score => Some((Vec::new(), true)),
+        (None, Some(_)) => Some((Vec::new(), true)),
+        (Some((_, path)), _) => Some((path, false)),
+        (None, None) => None,
+    }
+}
+
+// the bot plays the last board the keyboard plays, which makes it the opponent in versus,
+// online that is the board of this instance
+pub fn played_board(player_q: &Query<(Entity, &Board, Has<MainBoard>)>) -> Option<Entity> {
+    player_q
+        .iter()
+        .filter(|(_, board, main)| board.input.is_none() || *main)
+        .max_by_key(|(_, board, _)| board.player)
+        .map(|(field, ..)| field)
+}
+
+pub fn play(
+    mut keys: ResMut<ButtonInput<KeyCode>>,
+    mut bot: ResMut<Bot>,
+    board_q: Query<(&Board, &TetrisManager, &Stats, &GameState, &Children)>,
+    player_q: Query<(Entity, &Board, Has<MainBoard>)>,
+    rules: Res<Rules>,
+    view: BoardView,
+) {
+    if keys.just_pressed(BOT_KEY) {
+        bot.enabled = !bot.enabled;
+        bot.key = None;
+        info!("Bot {}", if bot.enabled { "enabled" } else { "disabled" });
+    }
+
+    let Some(Ok((board, manager, stats, game_state, field_children))) =
+        played_board(&player_q).map(|field| board_q.get(field))
+    else {
+        return;
+    };
+    if !bot.enabled || *game_state != GameState::Play {
+        bot.controller.release(&mut keys);
+        return;
+    }
+    if !bot.controller.release_tap(&mut keys, &board.controls) {
+        return;
+    }
+
+    let Some((active_tetris, transform)) = view.active(field_children) else {
+        return;
+    };
+    let current = Spot::from_active(active_tetris, transform, board, &rules);
+    let hold_field = view.hold(field_children);
+    let hold = hold_field.flatten();
+
+    // gravity or a failed move can take the tetris off the path
+    let key = Some((stats.pieces, hold));
+    if bot.key != key || !bot.controller.path.contains(&current) {
+        let grid = view.grid(board, field_children);
+        let hold_spawn = (rules.hold && !manager.hold && hold_field.is_some())
+            .then(|| grid.spawn(hold.unwrap_or_else(|| manager.next_tetris(0))))
+            .filter(|spawn| spawn.index != current.index);
+
+        bot.key = key;
+        bot.controller.path.clear();
+        match plan(&grid, current, hold_spawn, &rules) {
+            Some((_, true)) => {
+                bot.controller.press(&mut keys, board.controls.hold);
+                return;
+            }
+            Some((path, false)) => bot.controller.path = path,
+            None => return,
+        }
+    }
+
+    bot.controller
+        .follow(&mut keys, current, &rules, &board.controls);
+}
@@ -0,0 +1,46 @@
+use std::time::Duration;
+
+use crate::*;
+
+pub const MAX_START_LEVEL: u32 = 29;
+const FRAME_TIME: f32 = 1.0 / 60.0988;
+const SPAWN_DELAY: u32 = 10;
+const LINE_CLEAR_DELAY: u32 = 20;
+
+// frames per row for levels 0 to 28, every level after that falls a row every frame
+#[rustfmt::skip]
+const GRAVITY: [u32; 29] = [
+    48, 43, 38, 33, 28, 23, 18, 13, 8, 6,
+    5, 5, 5, 4, 4, 4, 3, 3, 3,
+    2, 2, 2, 2, 2, 2, 2, 2, 2, 2,
+];
+const LINE_SCORES: [u64; 4] = [40, 100, 300, 1200];
+
+fn frames(frames: u32) -> Duration {
+    Duration::from_secs_f32(frames as f32 * FRAME_TIME)
+}
+
+pub fn apply_speed(manager: &mut TetrisManager, level: u32) {
+    let fall_time = frames(GRAVITY.get(level as usize).copied().unwrap_or(1));
+    manager.fall_rows = 1;
+    manager.fall_timer.set_duration(fall_time);
+    // there is no lock delay, the tetris locks as soon as gravity can't move it anymore
+    manager.lock_timer.set_duration(fall_time);
+    manager.spawn_delay = frames(SPAWN_DELAY);
+    manager.line_clear_delay = frames(LINE_CLEAR_DELAY);
+}
+
+// the first level up needs more lines the higher the starting level is,
+// after that every 10 lines raise the level by one
+pub fn level(start_level: u32, lines: u32) -> u32 {
+    let first = (start_level * 10 + 10).min(100.max((start_level * 10).saturating_sub(50)));
+    if lines < first {
+        start_level
+    } else {
+        start_level + 1 + (lines - first) / 10
+    }
+}
+
+pub fn line_score(level: u32, lines: u32) -> u64 {
+    LINE_SCORES[lines.clamp(1, 4) as usize - 1] * (level as u64 + 1)
+}
@@ -0,0 +1,148 @@
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::field::Board;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Left,
+    Right,
+    SoftDrop,
+    HardDrop,
+    Rotate,
+    Hold,
+}
+pub const ACTIONS: [Action; 6] = [
+    Action::Left,
+    Action::Right,
+    Action::SoftDrop,
+    Action::HardDrop,
+    Action::Rotate,
+    Action::Hold,
+];
+
+// the actions held during one tick, one bit per action
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Inputs(pub u8);
+impl Inputs {
+    pub fn contains(self, action: Action) -> bool {
+        self.0 & 1 << action as u8 != 0
+    }
+    pub fn insert(&mut self, action: Action) {
+        self.0 |= 1 << action as u8;
+    }
+}
+
+// the inputs of a board driven tick by tick instead of by the local keyboard
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TickInput {
+    pub current: Inputs,
+    pub previous: Inputs,
+}
+
+// the keys and gamepad one player plays with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Controls {
+    pub left: KeyCode,
+    pub right: KeyCode,
+    pub soft_drop: KeyCode,
+    pub hard_drop: KeyCode,
+    pub rotate: KeyCode,
+    pub hold: KeyCode,
+    pub gamepad: Gamepad,
+}
+impl Controls {
+    pub const KEYBOARD: Self = Self {
+        left: KeyCode::ArrowLeft,
+        right: KeyCode::ArrowRight,
+        soft_drop: KeyCode::ArrowDown,
+        hard_drop: KeyCode::Space,
+        rotate: KeyCode::ArrowUp,
+        hold: KeyCode::KeyC,
+        gamepad: Gamepad { id: 0 },
+    };
+    // two players share the keyboard, the first one on the left side and the second one on the right
+    pub fn versus(player: usize) -> Self {
+        let gamepad = Gamepad { id: player };
+        if player == 0 {
+            Self {
+                left: KeyCode::KeyA,
+                right: KeyCode::KeyD,
+                soft_drop: KeyCode::KeyS,
+                hard_drop: KeyCode::Space,
+                rotate: KeyCode::KeyW,
+                hold: KeyCode::ShiftLeft,
+                gamepad,
+            }
+        } else {
+            Self {
+                hard_drop: KeyCode::Enter,
+                hold: KeyCode::ShiftRight,
+                gamepad,
+                ..Self::KEYBOARD
+            }
+        }
+    }
+    pub fn key(&self, action: Action) -> KeyCode {
+        match action {
+            Action::Left => self.left,
+            Action::Right => self.right,
+            Action::SoftDrop => self.soft_drop,
+            Action::HardDrop => self.hard_drop,
+            Action::Rotate => self.rotate,
+            Action::Hold => self.hold,
+        }
+    }
+    pub fn button(&self, action: Action) -> GamepadButton {
+        let button_type = match action {
+            Action::Left => GamepadButtonType::DPadLeft,
+            Action::Right => GamepadButtonType::DPadRight,
+            Action::SoftDrop => GamepadButtonType::DPadDown,
+            Action::HardDrop => GamepadButtonType::DPadUp,
+            Action::Rotate => GamepadButtonType::South,
+            Action::Hold => GamepadButtonType::West,
+        };
+        GamepadButton::new(self.gamepad, button_type)
+    }
+}
+
+// the keyboard and gamepad state the players act on
+#[derive(SystemParam)]
+pub struct PlayerInput<'w> {
+    keys: Res<'w, ButtonInput<KeyCode>>,
+    buttons: Res<'w, ButtonInput<GamepadButton>>,
+}
+impl PlayerInput<'_> {
+    pub fn pressed(&self, board: &Board, action: Action) -> bool {
+        match board.input {
+            Some(input) => input.current.contains(action),
+            None => self.is_held(&board.controls, action),
+        }
+    }
+    pub fn just_pressed(&self, board: &Board, action: Action) -> bool {
+        match board.input {
+            Some(input) => input.current.contains(action) && !input.previous.contains(action),
+            None => {
+                self.keys.just_pressed(board.controls.key(action))
+                    || self.buttons.just_pressed(board.controls.button(action))
+            }
+        }
+    }
+    fn is_held(&self, controls: &Controls, action: Action) -> bool {
+        self.keys.pressed(controls.key(action)) || self.buttons.pressed(controls.button(action))
+    }
+    // the actions held right now, a tap that started and ended within the frame still counts
+    pub fn sample(&self, controls: &Controls) -> Inputs {
+        let mut inputs = Inputs::default();
+        for action in ACTIONS {
+            if self.is_held(controls, action)
+                || self.keys.just_pressed(controls.key(action))
+                || self.buttons.just_pressed(controls.button(action))
+            {
+                inputs.insert(action);
+            }
+        }
+        inputs
+    }
+}
@@ -0,0 +1,30 @@
+use bevy::prelude::*;
+
+use crate::field::*;
+use crate::*;
+
+pub const DEFAULT_WIDTH: u32 = 16;
+// each player needs a half the widest tetris fits in
+pub const MIN_WIDTH: u32 = 12;
+pub const MAX_WIDTH: u32 = 24;
+
+// the shared field with the next and hold fields of both players on its sides
+pub fn view_width(size: UVec2) -> f32 {
+    field_size(size).x + (NEXT_FIELD_RECT.width() + HOLD_FIELD_RECT.width()) * 2.0 + 32.0
+}
+
+// the players top out together, once one of them can't spawn the game is over for both
+pub fn top_out(board_q: Query<(Entity, &Board)>, mut state_q: Query<&mut GameState>) {
+    for (field, board) in board_q.iter() {
+        let Some(partner) = board.partner else {
+            continue;
+        };
+        if *state_q.get(field).unwrap() != GameState::GameOver {
+            continue;
+        }
+        let mut partner_state = state_q.get_mut(partner).unwrap();
+        if *partner_state != GameState::GameOver {
+            *partner_state = GameState::GameOver;
+        }
+    }
+}
@@ -0,0 +1,408 @@
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::fs;
+
+use crate::field::*;
+use crate::fumen::{self, FUMEN_PREFIX};
+use crate::mode::*;
+use crate::practice::*;
+use crate::tetris::*;
+use crate::*;
+
+pub const SETUPS_PATH: &str = "./setups";
+const DEFAULT_SETUP_NAME: &str = "default";
+const PIECE_KEYS: [KeyCode; 7] = [
+    KeyCode::Digit1,
+    KeyCode::Digit2,
+    KeyCode::Digit3,
+    KeyCode::Digit4,
+    KeyCode::Digit5,
+    KeyCode::Digit6,
+    KeyCode::Digit7,
+];
+const GARBAGE_KEY: KeyCode = KeyCode::Digit8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Paint {
+    Tetris(usize),
+    Garbage,
+}
+impl Paint {
+    fn rect(&self) -> Rect {
+        let start = match self {
+            Self::Tetris(index) => BLOCK_RECT_START[*index],
+            Self::Garbage => GARBAGE_RECT_START,
+        };
+        Rect::from_corners(start, start + BLOCK_SIZE)
+    }
+    fn name(&self) -> char {
+        match self {
+            Self::Tetris(index) => TETRIS_NAMES[*index],
+            Self::Garbage => GARBAGE_NAME,
+        }
+    }
+    fn from_name(name: char) -> Option<Self> {
+        if name == GARBAGE_NAME {
+            return Some(Self::Garbage);
+        }
+        tetris_from_name(name).map(Self::Tetris)
+    }
+}
+
+#[derive(Resource)]
+pub struct Editor {
+    pub name: String,
+    pub paint: Paint,
+    pub hold: Option<usize>,
+    pub queue: Vec<usize>,
+}
+
+// a setup is saved as its hold and queue followed by the field from the top row down,
+// every cell is either a tetris name, the garbage name or '.' when empty
+//
+// hold T
+// queue SZLJ
+// ..........
+// ...
+struct Setup {
+    hold: Option<usize>,
+    queue: Vec<usize>,
+    cells: Vec<(u32, u32, Paint)>,
+}
+impl Setup {
+    fn parse(contents: &str) -> Self {
+        let mut setup = Setup {
+            hold: None,
+            queue: Vec::new(),
+            cells: Vec::new(),
+        };
+        let mut row = GRID_HEIGHT;
+        for line in contents.lines() {
+            if let Some(hold) = line.strip_prefix("hold ") {
+                setup.hold = hold.chars().next().and_then(tetris_from_name);
+            } else if let Some(queue) = line.strip_prefix("queue ") {
+                setup.queue = queue.chars().filter_map(tetris_from_name).collect();
+            } else if row > 0 {
+                row -= 1;
+                for (column, name) in line.chars().take(GRID_WIDTH as usize).enumerate() {
+                    if let Some(paint) = Paint::from_name(name) {
+                        setup.cells.push((column as u32, row, paint));
+                    }
+                }
+            }
+        }
+        setup
+    }
+    // the field of the first page, its piece becomes the first tetris of the queue
+    fn from_fumen(data: &str) -> Option<Self> {
+        let page = fumen::decode(data)?.into_iter().next()?;
+        let mut cells = Vec::new();
+        for row in 0..GRID_HEIGHT {
+            for column in 0..GRID_WIDTH {
+                let index =
+                    (fumen::FIELD_HEIGHT - 1 - row as usize) * fumen::FIELD_WIDTH + column as usize;
+                let paint = match page.field[index] {
+                    0 => continue,
+                    kind => fumen::tetris_index(kind).map_or(Paint::Garbage, Paint::Tetris),
+                };
+                cells.push((column, row, paint));
+            }
+        }
+        Some(Setup {
+            hold: None,
+            queue: page
+                .piece
+                .and_then(|piece| fumen::tetris_index(piece.kind))
+                .into_iter()
+                .collect(),
+            cells,
+        })
+    }
+    fn write(&self) -> String {
+        let mut contents = String::new();
+        if let Some(hold) = self.hold {
+            writeln!(contents, "hold {}", TETRIS_NAMES[hold]).unwrap();
+        }
+        contents.push_str("queue ");
+        contents.extend(self.queue.iter().map(|&index| TETRIS_NAMES[index]));
+        contents.push('\n');
+
+        let mut grid = vec![['.'; GRID_WIDTH as usize]; GRID_HEIGHT as usize];
+        for &(column, row, paint) in &self.cells {
+            grid[row as usize][column as usize] = paint.name();
+        }
+        for line in grid.iter().rev() {
+            contents.extend(line);
+            contents.push('\n');
+        }
+        contents
+    }
+}
+
+fn setup_path(name: &str) -> String {
+    format!("{}/{}", SETUPS_PATH, name)
+}
+
+fn spawn_cells(
+    commands: &mut Commands,
+    field: Entity,
+    sprite_handle: &Res<SpriteHandle>,
+    cells: &[(u32, u32, Paint)],
+) {
+    for &(column, row, paint) in cells {
+        let block = spawn_block(
+            commands,
+            field,
+            sprite_handle,
+            paint.rect(),
+            cell_position(STANDARD_SIZE, column, row),
+        );
+        if paint == Paint::Garbage {
+            commands.entity(block).insert(Garbage);
+        }
+    }
+}
+
+// the setup name or fumen given on the command line after the mode name
+#[derive(Resource)]
+pub struct SetupArg(pub Option<String>);
+
+pub fn setup(
+    mut commands: Commands,
+    mode: Res<GameMode>,
+    setup_arg: Res<SetupArg>,
+    sprite_handle: Res<SpriteHandle>,
+    mut field_q: Query<(Entity, &mut GameState), With<Field>>,
+    active_tetris_q: Query<Entity, With<ActiveTetris>>,
+    ghost_tetris_q: Query<Entity, With<GhostTetris>>,
+) {
+    if *mode != GameMode::Editor {
+        return;
+    }
+    let (field, mut game_state) = field_q.single_mut();
+
+    // usage: tetry edit [setup name | fumen]
+    let arg = setup_arg.0.clone().unwrap_or_default();
+    let (name, setup) = if arg.starts_with(FUMEN_PREFIX) {
+        let setup = Setup::from_fumen(&arg);
+        if setup.is_none() {
+            error!("Failed to load the fumen");
+        }
+        (DEFAULT_SETUP_NAME.to_string(), setup)
+    } else {
+        let name = Some(arg)
+            .filter(|name| {
+                !name.is_empty()
+                    && name
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+            })
+            .unwrap_or_else(|| DEFAULT_SETUP_NAME.to_string());
+        let setup = fs::read_to_string(setup_path(&name))
+            .ok()
+            .map(|contents| Setup::parse(&contents));
+        (name, setup)
+    };
+
+    let mut editor = Editor {
+        name,
+        paint: Paint::Tetris(crate::tetris::tetris::I),
+        hold: None,
+        queue: Vec::new(),
+    };
+    if let Some(setup) = setup {
+        info!("Loading setup {}", editor.name);
+        spawn_cells(&mut commands, field, &sprite_handle, &setup.cells);
+        editor.hold = setup.hold;
+        editor.queue = setup.queue;
+    }
+    commands.insert_resource(editor);
+
+    for tetris in active_tetris_q.iter().chain(ghost_tetris_q.iter()) {
+        commands.entity(tetris).insert(Visibility::Hidden);
+    }
+    *game_state = GameState::Edit;
+}
+
+// the point of the world under the mouse cursor
+#[derive(SystemParam)]
+pub struct Cursor<'w, 's> {
+    window_q: Query<'w, 's, &'static Window, With<PrimaryWindow>>,
+    camera_q: Query<'w, 's, (&'static Camera, &'static GlobalTransform)>,
+}
+impl Cursor<'_, '_> {
+    pub fn world_point(&self) -> Option<Vec2> {
+        let (camera, camera_transform) = self.camera_q.single();
+        self.window_q
+            .single()
+            .cursor_position()
+            .and_then(|cursor| camera.viewport_to_world_2d(camera_transform, cursor))
+    }
+}
+
+pub fn paint(
+    mut commands: Commands,
+    mouse_input: Res<ButtonInput<MouseButton>>,
+    editor: Res<Editor>,
+    sprite_handle: Res<SpriteHandle>,
+    cursor: Cursor,
+    field_q: Query<(Entity, &GlobalTransform), With<Field>>,
+    mut block_q: Query<(Entity, &Transform, &mut Sprite), With<Block>>,
+) {
+    let paint = mouse_input.pressed(MouseButton::Left);
+    let erase = mouse_input.pressed(MouseButton::Right);
+    if !paint && !erase {
+        return;
+    }
+
+    let (field, field_transform) = field_q.single();
+    let Some(point) = cursor.world_point() else {
+        return;
+    };
+    let Some((column, row)) = cell_at(
+        STANDARD_SIZE,
+        point - field_transform.translation().truncate(),
+    ) else {
+        return;
+    };
+    let position = cell_position(STANDARD_SIZE, column, row);
+
+    let existing = block_q
+        .iter_mut()
+        .find(|(_, transform, _)| transform.translation.truncate().distance(position) < 1.0);
+
+    match existing {
+        Some((entity, _, mut sprite)) => {
+            if erase {
+                commands.entity(entity).despawn();
+            } else if sprite.rect != Some(editor.paint.rect()) {
+                sprite.rect = Some(editor.paint.rect());
+                if editor.paint == Paint::Garbage {
+                    commands.entity(entity).insert(Garbage);
+                } else {
+                    commands.entity(entity).remove::<Garbage>();
+                }
+            }
+        }
+        None if paint => {
+            spawn_cells(
+                &mut commands,
+                field,
+                &sprite_handle,
+                &[(column, row, editor.paint)],
+            );
+        }
+        None => {}
+    }
+}
+
+pub fn edit(
+    mut commands: Commands,
+    button_input: Res<ButtonInput<KeyCode>>,
+    mut editor: ResMut<Editor>,
+    mut board_q: Query<(&mut TetrisManager, &mut GameState)>,
+    score_q: Query<&Score>,
+    block_q: Query<(&Transform, &Sprite, Has<Garbage>), With<Block>>,
+) {
+    for (index, key) in PIECE_KEYS.iter().enumerate() {
+        if button_input.just_pressed(*key) {
+            editor.paint = Paint::Tetris(index);
+        }
+    }
+    if button_input.just_pressed(GARBAGE_KEY) {
+        editor.paint = Paint::Garbage;
+    }
+
+    // holding garbage clears the hold
+    if button_input.just_pressed(KeyCode::KeyH) {
+        editor.hold = match editor.paint {
+            Paint::Tetris(index) => Some(index),
+            Paint::Garbage => None,
+        };
+    }
+    if button_input.just_pressed(KeyCode::KeyN) {
+        if let Paint::Tetris(index) = editor.paint {
+            editor.queue.push(index);
+        }
+    }
+    if button_input.just_pressed(KeyCode::Backspace) {
+        editor.queue.pop();
+    }
+
+    let cells = || {
+        block_q
+            .iter()
+            .filter_map(|(transform, sprite, garbage)| {
+                let (column, row) = cell_at(STANDARD_SIZE, transform.translation.truncate())?;
+                let paint = if garbage {
+                    Paint::Garbage
+                } else {
+                    let start = sprite.rect?.min;
+                    Paint::Tetris(BLOCK_RECT_START.iter().position(|&s| s == start)?)
+                };
+                Some((column, row, paint))
+            })
+            .collect::<Vec<_>>()
+    };
+
+    if button_input.just_pressed(KeyCode::KeyS) {
+        let setup = Setup {
+            hold: editor.hold,
+            queue: editor.queue.clone(),
+            cells: cells(),
+        };
+        let path = setup_path(&editor.name);
+        match fs::create_dir_all(SETUPS_PATH).and_then(|_| fs::write(&path, setup.write())) {
+            Ok(()) => info!("Saved setup to {}", path),
+            Err(err) => error!("Failed to save setup: {}", err),
+        }
+    }
+
+    if !button_input.just_pressed(KeyCode::Enter) {
+        return;
+    }
+
+    // the explicit queue comes first, the randomizer takes over after it
+    let (mut manager, mut game_state) = board_q.single_mut();
+    let mut queue: VecDeque<usize> = editor.queue.iter().copied().collect();
+    queue.extend(manager.queue.iter().copied());
+    manager.queue = queue;
+
+    let blocks = block_q
+        .iter()
+        .map(|(transform, sprite, garbage)| PlacedBlock {
+            position: transform.translation.truncate(),
+            rect: sprite.rect.unwrap(),
+            garbage,
+        })
+        .collect();
+    commands.insert_resource(Restore(Snapshot {
+        blocks,
+        queue: manager.queue.clone(),
+        active: manager.current_tetris(),
+        hold: editor.hold,
+        hold_used: false,
+        score: Score::new(score_q.single().best_score),
+        stats: Stats::default(),
+    }));
+    *game_state = GameState::Play;
+}
+
+pub fn update_text(editor: Res<Editor>, mut text_q: Query<&mut Text, With<ModeText>>) {
+    let Ok(mut text) = text_q.get_single_mut() else {
+        return;
+    };
+
+    let value = &mut text.sections[0].value;
+    value.clear();
+
+    write!(value, "Paint\n{}\n\nHold\n", editor.paint.name()).unwrap();
+    value.push(editor.hold.map_or('-', |index| TETRIS_NAMES[index]));
+    value.push_str("\n\nQueue\n");
+    value.extend(editor.queue.iter().map(|&index| TETRIS_NAMES[index]));
+    value.push_str("\n\n1-8 paint\nH hold\nN queue\nS save\nF1 fumen\nEnter play");
+}
@@ -4,15 +4,41 @@ use bevy::prelude::*;
 use bevy::sprite::*;
 
 use std::fmt::Write;
-use std::fs;
 
+use crate::controls::*;
+use crate::mode::*;
+use crate::pieces::*;
+use crate::rules::*;
 use crate::tetris;
 use crate::*;
 
 pub const GRID_WIDTH: u32 = 10;
 pub const GRID_HEIGHT: u32 = 20;
-pub const FIELD_RECT: Rect = Rect {
+// columns and rows of the field most modes are played on
+pub const STANDARD_SIZE: UVec2 = uvec2(GRID_WIDTH, GRID_HEIGHT);
+pub const MIN_SIZE: UVec2 = uvec2(4, 4);
+pub const MAX_SIZE: UVec2 = uvec2(40, 60);
+pub const SIZE_ARG: &str = "--field";
+// the frame is put together from tiles of the field sprite, a wall tile next to every row,
+// a floor tile below every column and the two bottom corners
+const LEFT_WALL_RECT: Rect = Rect {
     min: vec2(64.0, 0.0),
+    max: vec2(80.0, 8.0),
+};
+const RIGHT_WALL_RECT: Rect = Rect {
+    min: vec2(160.0, 0.0),
+    max: vec2(176.0, 8.0),
+};
+const FLOOR_RECT: Rect = Rect {
+    min: vec2(80.0, 160.0),
+    max: vec2(88.0, 176.0),
+};
+const LEFT_CORNER_RECT: Rect = Rect {
+    min: vec2(64.0, 160.0),
+    max: vec2(80.0, 176.0),
+};
+const RIGHT_CORNER_RECT: Rect = Rect {
+    min: vec2(160.0, 160.0),
     max: vec2(176.0, 176.0),
 };
 pub const NEXT_FIELD_RECT: Rect = Rect {
@@ -20,16 +46,77 @@ pub const NEXT_FIELD_RECT: Rect = Rect {
     max: vec2(64.0, 64.0),
 };
 pub const HOLD_FIELD_RECT: Rect = NEXT_FIELD_RECT;
+// previews after the first one are drawn smaller in a column below the next field
+pub const NEXT_QUEUE_SCALE: f32 = 0.5;
+pub const NEXT_QUEUE_SPACING: f32 = 16.0;
+// the walls and the floor of the frame around the grid
+const FIELD_BORDER: f32 = 16.0;
+// horizontal distance between the centers of side by side boards on standard fields
+pub const BOARD_SPACING: f32 = 224.0;
+
+// columns and rows of the fields of the game
+#[derive(Resource, Clone, Copy)]
+pub struct FieldSize(pub UVec2);
 
 #[derive(Component)]
 pub struct Field;
 
+// a tile of the frame around a field
+#[derive(Component)]
+pub struct FrameTile;
+
+// the field entity of a player, it also keeps the manager, stats and score of the board
+// while the next and hold fields, the score text and every tetris and block are its children
+#[derive(Component)]
+pub struct Board {
+    pub player: usize,
+    pub controls: Controls,
+    // set when the board follows inputs from the network or a bot instead of the controls
+    pub input: Option<TickInput>,
+    // columns and rows of the field, co-op plays on a wider one
+    pub size: UVec2,
+    // blocks across every cell of a tetris, big mode makes them 2 by 2 blocks
+    pub cell_blocks: u32,
+    // columns right of the middle the tetrises spawn at, each co-op player spawns in their half
+    pub spawn_column: i32,
+    // in co-op the board of the first player, whose field keeps the blocks of both players
+    pub shared: Option<Entity>,
+    // in co-op the other player, their active tetris is in the way of this one
+    pub partner: Option<Entity>,
+}
+impl Board {
+    // columns and rows the tetrises move in, in big mode every one of them is 2 of the field
+    pub fn play_size(&self) -> UVec2 {
+        self.size / self.cell_blocks
+    }
+    // the distance a tetris moves in a single step
+    pub fn cell_size(&self) -> Vec2 {
+        tetris::BLOCK_SIZE * self.cell_blocks as f32
+    }
+    // the scale of the tetrises on the field
+    pub fn tetris_scale(&self) -> Vec3 {
+        Vec3::splat(self.cell_blocks as f32)
+    }
+    pub fn spawn_position(&self, piece: &Piece) -> Vec2 {
+        // odd widths have no column in the middle, the tetrises go half a cell right of it
+        let column = self.spawn_column as f32 + (self.play_size().x % 2) as f32 * 0.5;
+        (piece.spawn + vec2(column, 0.0)) * self.cell_size()
+    }
+}
+
+// the board the local player watches, the practice tools, hints and fumen export follow it
+#[derive(Component)]
+pub struct MainBoard;
+
 #[derive(Component)]
 pub struct NextField;
 
 #[derive(Component)]
 pub struct HoldField;
 
+#[derive(Component)]
+pub struct ScoreText;
+
 #[derive(Component, Clone, Copy)]
 pub struct Score {
     pub best_score: u64,
@@ -48,122 +135,314 @@ pub fn setup(
     mut commands: Commands,
     sprite_handle: Res<SpriteHandle>,
     font_handle: Res<FontHandle>,
+    mode: Res<GameMode>,
+    rules: Res<Rules>,
+    size: Res<FieldSize>,
 ) {
     let text_style = TextStyle {
         font: font_handle.0.clone(),
         font_size: 200.0,
         color: Color::WHITE,
     };
-    commands.spawn((
-        SpriteBundle {
-            texture: sprite_handle.0.clone(),
-            sprite: Sprite {
-                rect: Some(FIELD_RECT),
-                anchor: Anchor::TopCenter,
-                ..Default::default()
-            },
-            transform: Transform::from_translation(vec3(0.0, tetris::GRID_SIZE.y * 0.5, 0.0)),
-            ..Default::default()
-        },
-        Field,
-    ));
-    commands
-        .spawn((
-            SpriteBundle {
-                texture: sprite_handle.0.clone(),
-                sprite: Sprite {
-                    rect: Some(NEXT_FIELD_RECT),
-                    ..Default::default()
-                },
-                transform: Transform {
-                    translation: (FIELD_RECT.size() * 0.5
-                        + NEXT_FIELD_RECT.size() * vec2(0.5, -0.5)
-                        + vec2(0.0, -16.0))
-                    .extend(0.0),
-                    ..Default::default()
-                },
-                ..Default::default()
+    let players = mode.players();
+    let royale = matches!(*mode, GameMode::Royale { .. });
+    let coop = matches!(*mode, GameMode::Coop { .. });
+    let size = size.0;
+    let frame_size = field_size(size);
+    let spacing = board_spacing(size);
+    // the co-op players need each other's entities before either board is spawned
+    let boards: Vec<Entity> = (0..players).map(|_| commands.spawn_empty().id()).collect();
+    for player in 0..players {
+        // online boards are both played from the keyboard, each on its own instance,
+        // and in battle royale the keyboard only plays the first board
+        let controls = if players == 1 || royale || *mode == GameMode::Online {
+            Controls::KEYBOARD
+        } else {
+            Controls::versus(player)
+        };
+        let transform = if royale {
+            royale::board_transform(player)
+        } else if coop {
+            Transform::from_translation(field_top(size).extend(0.0))
+        } else {
+            let origin =
+                field_top(size) + vec2((player as f32 - (players - 1) as f32 * 0.5) * spacing, 0.0);
+            Transform::from_translation(origin.extend(0.0))
+        };
+        // the bot boards of battle royale are only small fields
+        let mini = royale && player > 0;
+        // the second co-op player plays in the field of the first one, so their board has no field
+        // of its own and keeps its next and hold fields on the right while the first keeps them
+        // on the left
+        let (shared, partner) = if coop {
+            (
+                (player > 0).then_some(boards[0]),
+                Some(boards[(player + 1) % players]),
+            )
+        } else {
+            (None, None)
+        };
+        let left = coop && player == 0;
+        let right = coop && player > 0;
+        let cell_blocks = if rules.big { 2 } else { 1 };
+        let spawn_column = if coop {
+            // the middle of each half
+            ((size.x / cell_blocks) as i32 + 2) / 4 * if left { -1 } else { 1 }
+        } else {
+            0
+        };
+        let field = boards[player];
+        commands.entity(field).insert((
+            SpatialBundle::from_transform(transform),
+            Board {
+                player,
+                controls,
+                input: None,
+                size,
+                cell_blocks,
+                spawn_column,
+                shared,
+                partner,
             },
-            NextField,
-        ))
-        .with_children(|builder| {
-            builder.spawn(Text2dBundle {
-                text: Text {
-                    sections: vec![TextSection::new("Next", text_style.clone())],
-                    ..Default::default()
-                },
-                text_anchor: Anchor::BottomCenter,
-                transform: Transform {
-                    translation: (NEXT_FIELD_RECT.size() * vec2(0.0, 0.5)).extend(0.0),
-                    scale: Vec3::splat(12.0 / text_style.font_size),
-                    ..Default::default()
-                },
-                ..Default::default()
-            });
-        });
-    commands
-        .spawn((
-            SpriteBundle {
-                texture: sprite_handle.0.clone(),
-                sprite: Sprite {
-                    rect: Some(HOLD_FIELD_RECT),
-                    ..Default::default()
-                },
-                transform: Transform {
-                    translation: (FIELD_RECT.size() * vec2(-0.5, 0.5)
-                        + HOLD_FIELD_RECT.size() * vec2(-0.5, -0.5)
-                        + vec2(0.0, -16.0))
-                    .extend(0.0),
-                    ..Default::default()
-                },
-                ..Default::default()
-            },
-            HoldField,
-        ))
-        .with_children(|builder| {
-            builder.spawn(Text2dBundle {
-                text: Text {
-                    sections: vec![TextSection::new("Hold", text_style.clone())],
+            TetrisManager::new(&rules),
+            Stats::default(),
+            Score::new(0),
+            GameState::Play,
+        ));
+        if shared.is_none() {
+            commands.entity(field).insert(Field);
+            spawn_frame(&mut commands, &sprite_handle, field, size);
+        }
+        if player == 0 {
+            commands.entity(field).insert(MainBoard);
+        }
+        if mini {
+            continue;
+        }
+        if rules.preview_count > 0 {
+            commands
+                .spawn((
+                    SpriteBundle {
+                        texture: sprite_handle.0.clone(),
+                        sprite: Sprite {
+                            rect: Some(NEXT_FIELD_RECT),
+                            ..Default::default()
+                        },
+                        transform: Transform {
+                            translation: (frame_size * vec2(0.5, 0.0)
+                                + NEXT_FIELD_RECT.size() * vec2(0.5, -0.5)
+                                + vec2(0.0, -8.0)
+                                - if left {
+                                    vec2(frame_size.x + HOLD_FIELD_RECT.width(), 0.0)
+                                        + NEXT_FIELD_RECT.size() * vec2(1.0, 0.0)
+                                } else {
+                                    Vec2::ZERO
+                                })
+                            .extend(0.0),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    },
+                    NextField,
+                ))
+                .set_parent(field)
+                .with_children(|builder| {
+                    builder.spawn(Text2dBundle {
+                        text: Text {
+                            sections: vec![TextSection::new("Next", text_style.clone())],
+                            ..Default::default()
+                        },
+                        text_anchor: Anchor::BottomCenter,
+                        transform: Transform {
+                            translation: (NEXT_FIELD_RECT.size() * vec2(0.0, 0.5)).extend(0.0),
+                            scale: Vec3::splat(12.0 / text_style.font_size),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    });
+                });
+        }
+        if rules.hold {
+            commands
+                .spawn((
+                    SpriteBundle {
+                        texture: sprite_handle.0.clone(),
+                        sprite: Sprite {
+                            rect: Some(HOLD_FIELD_RECT),
+                            ..Default::default()
+                        },
+                        transform: Transform {
+                            translation: (frame_size * vec2(-0.5, 0.0)
+                                + HOLD_FIELD_RECT.size() * vec2(-0.5, -0.5)
+                                + vec2(0.0, -8.0)
+                                + if right {
+                                    vec2(frame_size.x + NEXT_FIELD_RECT.width(), 0.0)
+                                        + HOLD_FIELD_RECT.size() * vec2(1.0, 0.0)
+                                } else {
+                                    Vec2::ZERO
+                                })
+                            .extend(0.0),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    },
+                    HoldField,
+                ))
+                .set_parent(field)
+                .with_children(|builder| {
+                    builder.spawn(Text2dBundle {
+                        text: Text {
+                            sections: vec![TextSection::new("Hold", text_style.clone())],
+                            ..Default::default()
+                        },
+                        text_anchor: Anchor::BottomCenter,
+                        transform: Transform {
+                            translation: (HOLD_FIELD_RECT.size() * vec2(0.0, 0.5)).extend(0.0),
+                            scale: Vec3::splat(12.0 / text_style.font_size),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    });
+                });
+        }
+        commands
+            .spawn((
+                Text2dBundle {
+                    text: Text {
+                        sections: vec![TextSection::new(
+                            "Score\n0\n\nBest score\n0",
+                            text_style.clone(),
+                        )],
+                        justify: if left {
+                            JustifyText::Right
+                        } else {
+                            JustifyText::Left
+                        },
+                        ..Default::default()
+                    },
+                    text_anchor: if left {
+                        Anchor::CenterRight
+                    } else {
+                        Anchor::CenterLeft
+                    },
+                    transform: Transform {
+                        translation: (tetris::grid_size(size) * vec2(0.0, -0.5)
+                            + frame_size * vec2(if left { -0.5 } else { 0.5 }, 0.0))
+                        .extend(0.0),
+                        scale: Vec3::splat(8.0 / text_style.font_size),
+                        ..Default::default()
+                    },
                     ..Default::default()
                 },
-                text_anchor: Anchor::BottomCenter,
-                transform: Transform {
-                    translation: (HOLD_FIELD_RECT.size() * vec2(0.0, 0.5)).extend(0.0),
-                    scale: Vec3::splat(12.0 / text_style.font_size),
+                ScoreText,
+            ))
+            .set_parent(field);
+    }
+}
+// the field entity sits at the top center of the grid, the rest of the board is placed relative to it
+pub fn field_top(size: UVec2) -> Vec2 {
+    vec2(0.0, tetris::grid_size(size).y * 0.5)
+}
+// the size of the frame around a grid with the given columns and rows
+pub fn field_size(size: UVec2) -> Vec2 {
+    tetris::grid_size(size) + vec2(FIELD_BORDER * 2.0, FIELD_BORDER)
+}
+// boards on wider fields move apart by the extra columns
+pub fn board_spacing(size: UVec2) -> f32 {
+    BOARD_SPACING + (size.x as f32 - GRID_WIDTH as f32) * tetris::BLOCK_SIZE.x
+}
+
+fn spawn_frame(
+    commands: &mut Commands,
+    sprite_handle: &Res<SpriteHandle>,
+    field: Entity,
+    size: UVec2,
+) {
+    let grid_size = tetris::grid_size(size);
+    let mut spawn_tile = |rect: Rect, position: Vec2| {
+        commands
+            .spawn((
+                SpriteBundle {
+                    texture: sprite_handle.0.clone(),
+                    sprite: Sprite {
+                        rect: Some(rect),
+                        ..Default::default()
+                    },
+                    transform: Transform::from_translation(position.extend(0.0)),
                     ..Default::default()
                 },
-                ..Default::default()
-            });
-        });
-    commands.spawn((
-        Text2dBundle {
-            text: Text {
-                sections: vec![
-                    TextSection::new("Score\n0\n\nBest score\n0", text_style.clone()),
-                ],
-                justify: JustifyText::Left,
-                ..Default::default()
-            },
-            text_anchor: Anchor::CenterLeft,
-            transform: Transform {
-                translation: (FIELD_RECT.size() * vec2(0.5, 0.0)).extend(0.0),
-                scale: Vec3::splat(8.0 / text_style.font_size),
-                ..Default::default()
-            },
-            ..Default::default()
-        },
-        Score::new(0),
-    ));
+                FrameTile,
+            ))
+            .set_parent(field);
+    };
+    let wall_x = (grid_size.x + FIELD_BORDER) * 0.5;
+    for row in 0..size.y {
+        let y = tetris::cell_position(size, 0, row).y;
+        spawn_tile(LEFT_WALL_RECT, vec2(-wall_x, y));
+        spawn_tile(RIGHT_WALL_RECT, vec2(wall_x, y));
+    }
+    let floor_y = -grid_size.y - FIELD_BORDER * 0.5;
+    for column in 0..size.x {
+        let x = tetris::cell_position(size, column, 0).x;
+        spawn_tile(FLOOR_RECT, vec2(x, floor_y));
+    }
+    spawn_tile(LEFT_CORNER_RECT, vec2(-wall_x, floor_y));
+    spawn_tile(RIGHT_CORNER_RECT, vec2(wall_x, floor_y));
 }
-pub fn load_score(mut score_q: Query<&mut Score>) {
-    let mut score = score_q.single_mut();
-    score.best_score = fs::read_to_string("./score").map(|s| s.parse::<u64>().unwrap()).unwrap_or(0);
+
+// a size like 12x30, clamped to the fields the game can have
+pub fn parse_size(arg: &str) -> Option<UVec2> {
+    let (columns, rows) = arg.split_once('x')?;
+    let size = uvec2(columns.parse().ok()?, rows.parse().ok()?);
+    Some(size.clamp(MIN_SIZE, MAX_SIZE))
+}
+
+// big cells take 2 columns and 2 rows, so big mode drops the odd ones
+pub fn big_size(size: UVec2) -> UVec2 {
+    size / 2 * 2
 }
-pub fn update_score(mut score_q: Query<(&Score, &mut Text), Changed<Score>>) {
-    for (score, mut text) in score_q.iter_mut() {
+
+pub fn load_score(mut score_q: Query<&mut Score>, leaderboard: Res<Leaderboard>) {
+    for mut score in score_q.iter_mut() {
+        score.best_score = leaderboard.scores.first().copied().unwrap_or(0);
+    }
+}
+pub fn update_score(
+    board_q: Query<(&Score, &Children), Changed<Score>>,
+    mut text_q: Query<&mut Text, With<ScoreText>>,
+) {
+    for (score, field_children) in board_q.iter() {
         info!("Updating score");
 
+        let Some(text) = tetris::board_child(field_children, &text_q) else {
+            continue;
+        };
+        let mut text = text_q.get_mut(text).unwrap();
         text.sections[0].value.clear();
-        write!(text.sections[0].value, "Score\n{}\n\nBest Score\n{}", score.current_score, score.best_score).unwrap();
+        write!(
+            text.sections[0].value,
+            "Score\n{}\n\nBest Score\n{}",
+            score.current_score, score.best_score
+        )
+        .unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_size_clamps() {
+        assert_eq!(parse_size("10x20"), Some(uvec2(10, 20)));
+        assert_eq!(parse_size("4x4"), Some(MIN_SIZE));
+        assert_eq!(parse_size("40x60"), Some(MAX_SIZE));
+        // sizes outside the limits are clamped to them
+        assert_eq!(parse_size("2x100"), Some(uvec2(4, 60)));
+        assert_eq!(parse_size("99x0"), Some(uvec2(40, 4)));
+        assert_eq!(parse_size("10"), None);
+        assert_eq!(parse_size("10x"), None);
+        assert_eq!(parse_size("-1x20"), None);
+        assert_eq!(parse_size("ax20"), None);
     }
 }
@@ -0,0 +1,174 @@
+use bevy::prelude::*;
+
+use std::collections::{HashMap, VecDeque};
+
+use crate::field::*;
+use crate::grid::*;
+use crate::mode::*;
+use crate::rules::*;
+use crate::tetris::*;
+use crate::*;
+
+// the fewest slides and rotations that get a spawned tetris above where it drops into the spot,
+// None when it has to be tucked or spun in
+pub fn min_inputs(grid: &Grid, target: &Spot, rules: &Rules) -> Option<u32> {
+    let mut target_cells = target.cells(rules);
+    target_cells.sort();
+
+    let start = grid.above_stack(grid.spawn(target.index), rules);
+    let mut inputs = HashMap::from([(start, 0)]);
+    let mut queue = VecDeque::from([start]);
+
+    while let Some(spot) = queue.pop_front() {
+        let mut cells = grid.drop(spot, rules).cells(rules);
+        cells.sort();
+        if cells == target_cells {
+            return Some(inputs[&spot]);
+        }
+
+        let mut moves = vec![spot.moved(-1, 0), spot.moved(1, 0)];
+        moves.extend(grid.rotated(&spot, rules));
+        // holding a direction slides all the way
+        for direction in [-1, 1] {
+            let mut slid = spot;
+            while grid.fits(&slid.moved(direction, 0), rules) {
+                slid = slid.moved(direction, 0);
+            }
+            moves.push(slid);
+        }
+
+        for next in moves {
+            if grid.fits(&next, rules) && !inputs.contains_key(&next) {
+                inputs.insert(next, inputs[&spot] + 1);
+                queue.push_back(next);
+            }
+        }
+    }
+    None
+}
+
+// the finesse mode asks for random placements on an empty field
+#[derive(Resource, Default)]
+pub struct Trainer {
+    pub target: Option<Spot>,
+    // the last placement missed, the same tetris comes again
+    pub retry: bool,
+}
+
+#[derive(Component)]
+pub struct TargetTetris;
+
+pub fn setup(mut commands: Commands, mode: Res<GameMode>) {
+    if *mode == GameMode::Finesse {
+        commands.insert_resource(Trainer::default());
+    }
+}
+
+// runs right before the active tetris gets placed
+pub fn check(
+    rules: Res<Rules>,
+    mut board_q: Query<(&Board, &TetrisManager, &mut Stats, &GameState, &Children)>,
+    mut trainer: Option<ResMut<Trainer>>,
+    view: BoardView,
+) {
+    for (board, manager, mut stats, game_state, field_children) in board_q.iter_mut() {
+        if *game_state != GameState::Place {
+            continue;
+        }
+        let Some((active_tetris, transform)) = view.active(field_children) else {
+            continue;
+        };
+        let spot = Spot::from_active(active_tetris, transform, board, &rules);
+        let grid = view.grid(board, field_children);
+
+        let fault = match min_inputs(&grid, &spot, &rules) {
+            Some(min) if manager.inputs > min => {
+                info!(
+                    "Finesse fault, {} inputs instead of {}",
+                    manager.inputs, min
+                );
+                true
+            }
+            _ => false,
+        };
+
+        let Some(trainer) = trainer.as_mut() else {
+            stats.finesse_faults += fault as u32;
+            continue;
+        };
+        let mut cells = spot.cells(&rules);
+        let mut target_cells = trainer
+            .target
+            .map_or(cells.clone(), |target| target.cells(&rules));
+        cells.sort();
+        target_cells.sort();
+        trainer.retry = fault || cells != target_cells;
+        stats.finesse_faults += trainer.retry as u32;
+    }
+}
+
+pub fn pick_target(
+    mut trainer: ResMut<Trainer>,
+    rules: Res<Rules>,
+    board_q: Query<&Board, With<MainBoard>>,
+    active_tetris_q: Query<&ActiveTetris>,
+) {
+    if trainer.target.is_some() {
+        return;
+    }
+    let placements = Grid::new(board_q.single()).placements(active_tetris_q.single().index, &rules);
+    if !placements.is_empty() {
+        trainer.target = Some(placements[fastrand::usize(..placements.len())]);
+    }
+}
+
+// every target starts on an empty field, a missed one is asked for again
+pub fn next_target(
+    mut commands: Commands,
+    mut trainer: ResMut<Trainer>,
+    mut manager_q: Query<&mut TetrisManager>,
+    active_tetris_q: Query<&ActiveTetris>,
+    block_q: Query<Entity, With<Block>>,
+) {
+    for entity in block_q.iter() {
+        commands.entity(entity).despawn();
+    }
+    if trainer.retry {
+        let mut manager = manager_q.single_mut();
+        manager.queue.push_front(active_tetris_q.single().index);
+        trainer.retry = false;
+    } else {
+        trainer.target = None;
+    }
+}
+
+pub fn update_overlay(
+    mut commands: Commands,
+    trainer: Res<Trainer>,
+    sprite_handle: Res<SpriteHandle>,
+    rules: Res<Rules>,
+    board_q: Query<(Entity, &Board), With<MainBoard>>,
+    target_tetris_q: Query<Entity, With<TargetTetris>>,
+) {
+    if !trainer.is_changed() {
+        return;
+    }
+    for entity in target_tetris_q.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    let Some(target) = trainer.target else {
+        return;
+    };
+    let (field, board) = board_q.single();
+    let tetris = spawn_spot(
+        &mut commands,
+        &sprite_handle,
+        field,
+        board,
+        &target,
+        &rules,
+        Color::rgba(1.0, 0.8, 0.3, 0.5),
+    );
+    commands.entity(tetris).insert(TargetTetris);
+}
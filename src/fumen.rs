@@ -0,0 +1,685 @@
+use bevy::prelude::*;
+
+use std::fs;
+
+use crate::field::{Board, MainBoard, STANDARD_SIZE};
+use crate::mode::*;
+use crate::rules::Rules;
+use crate::tetris::*;
+use crate::GameState;
+
+// fumen (v115) is the format the community uses to share boards, see https://github.com/knewjade/tetris-fumen
+pub const FUMEN_PREFIX: &str = "v115@";
+pub const BOARD_PATH: &str = "./board.fumen";
+pub const REPLAY_PATH: &str = "./replay.fumen";
+
+const ENCODE_TABLE: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const COMMENT_TABLE: &[u8; 95] = b" !\"#$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstuvwxyz{|}~";
+const COMMENT_BASE: u32 = COMMENT_TABLE.len() as u32 + 1;
+const LINE_LENGTH: usize = 47;
+
+pub const FIELD_WIDTH: usize = 10;
+// visible rows, there is one more garbage row below them
+pub const FIELD_HEIGHT: usize = 23;
+pub const FIELD_CELLS: usize = FIELD_WIDTH * (FIELD_HEIGHT + 1);
+
+// fumen colors in tetris index order, 0 is empty and 8 is garbage
+pub const FUMEN_TYPES: [u8; 7] = [1, 3, 5, 7, 4, 6, 2];
+pub const FUMEN_GARBAGE: u8 = 8;
+
+// cells of every fumen type in its spawn rotation, relative to its center
+#[rustfmt::skip]
+const FUMEN_SHAPES: [[[i32; 2]; 4]; 7] = [
+    [[0, 0], [-1, 0], [1, 0], [2, 0]],  // I
+    [[0, 0], [-1, 0], [1, 0], [1, 1]],  // L
+    [[0, 0], [1, 0], [0, 1], [1, 1]],   // O
+    [[0, 0], [1, 0], [0, 1], [-1, 1]],  // Z
+    [[0, 0], [-1, 0], [1, 0], [0, 1]],  // T
+    [[0, 0], [-1, 0], [1, 0], [-1, 1]], // J
+    [[0, 0], [-1, 0], [0, 1], [1, 1]],  // S
+];
+
+// cells are indexed from the top row, x and y are counted from the bottom left,
+// the garbage row below the field has a y of -1
+pub type Field = [u8; FIELD_CELLS];
+
+fn field_index(x: i32, y: i32) -> Option<usize> {
+    if !(0..FIELD_WIDTH as i32).contains(&x) || !(-1..FIELD_HEIGHT as i32).contains(&y) {
+        return None;
+    }
+    Some((FIELD_HEIGHT as i32 - 1 - y) as usize * FIELD_WIDTH + x as usize)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rotation {
+    Reverse = 0,
+    Right = 1,
+    Spawn = 2,
+    Left = 3,
+}
+impl Rotation {
+    const ALL: [Rotation; 4] = [Self::Reverse, Self::Right, Self::Spawn, Self::Left];
+
+    fn apply(&self, [x, y]: [i32; 2]) -> [i32; 2] {
+        match self {
+            Self::Spawn => [x, y],
+            Self::Right => [y, -x],
+            Self::Reverse => [-x, -y],
+            Self::Left => [-y, x],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Piece {
+    // fumen type from 1 to 7
+    pub kind: u8,
+    pub rotation: Rotation,
+    pub x: i32,
+    pub y: i32,
+}
+impl Piece {
+    pub fn cells(&self) -> [[i32; 2]; 4] {
+        FUMEN_SHAPES[self.kind as usize - 1].map(|offset| {
+            let [x, y] = self.rotation.apply(offset);
+            [self.x + x, self.y + y]
+        })
+    }
+    // finds the piece that covers exactly the given cells
+    pub fn from_cells(kind: u8, cells: &[[i32; 2]; 4]) -> Option<Self> {
+        for rotation in Rotation::ALL {
+            for &[x, y] in cells {
+                let [offset_x, offset_y] = rotation.apply(FUMEN_SHAPES[kind as usize - 1][1]);
+                let piece = Self {
+                    kind,
+                    rotation,
+                    x: x - offset_x,
+                    y: y - offset_y,
+                };
+                let mut piece_cells = piece.cells();
+                let mut cells = *cells;
+                piece_cells.sort();
+                cells.sort();
+                if piece_cells == cells {
+                    return Some(piece);
+                }
+            }
+        }
+        None
+    }
+    // fumen stores some rotations with a different center than the one the shapes use, the offset
+    // goes from the stored coordinate to the center
+    fn coordinate_offset(&self) -> [i32; 2] {
+        const I: u8 = 1;
+        const O: u8 = 3;
+        const Z: u8 = 4;
+        const S: u8 = 7;
+        match (self.kind, self.rotation) {
+            (O, Rotation::Left) => [1, -1],
+            (O, Rotation::Reverse) => [1, 0],
+            (O, Rotation::Spawn) => [0, -1],
+            (I, Rotation::Reverse) => [1, 0],
+            (I, Rotation::Left) => [0, -1],
+            (S, Rotation::Spawn) => [0, -1],
+            (S, Rotation::Right) => [-1, 0],
+            (Z, Rotation::Spawn) => [0, -1],
+            (Z, Rotation::Left) => [1, 0],
+            _ => [0, 0],
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Page {
+    pub field: Field,
+    pub piece: Option<Piece>,
+    // a locked piece is placed into the field of the next page
+    pub lock: bool,
+    pub comment: Option<String>,
+}
+
+// the field the next page starts from
+fn next_field(page: &Page, rise: bool, mirror: bool) -> Field {
+    let mut field = page.field;
+    if !page.lock {
+        return field;
+    }
+
+    if let Some(piece) = page.piece {
+        for [x, y] in piece.cells() {
+            if let Some(index) = field_index(x, y) {
+                field[index] = piece.kind;
+            }
+        }
+    }
+
+    // clear the full rows of the visible field from the bottom up
+    let mut rows: Vec<[u8; FIELD_WIDTH]> = (0..FIELD_HEIGHT as i32)
+        .map(|y| {
+            let start = field_index(0, y).unwrap();
+            field[start..start + FIELD_WIDTH].try_into().unwrap()
+        })
+        .filter(|row: &[u8; FIELD_WIDTH]| row.contains(&0))
+        .collect();
+    let garbage_start = field_index(0, -1).unwrap();
+    let mut garbage: [u8; FIELD_WIDTH] = field[garbage_start..].try_into().unwrap();
+
+    if rise {
+        rows.insert(0, garbage);
+        garbage = [0; FIELD_WIDTH];
+    }
+    if mirror {
+        for row in rows.iter_mut() {
+            row.reverse();
+        }
+    }
+
+    let mut field = [0; FIELD_CELLS];
+    for (y, row) in rows.iter().take(FIELD_HEIGHT).enumerate() {
+        let start = field_index(0, y as i32).unwrap();
+        field[start..start + FIELD_WIDTH].copy_from_slice(row);
+    }
+    field[garbage_start..].copy_from_slice(&garbage);
+    field
+}
+
+fn escape(comment: &str) -> String {
+    let mut escaped = String::new();
+    for c in comment.chars() {
+        if c.is_ascii_alphanumeric() || "@*_+-./".contains(c) {
+            escaped.push(c);
+        } else if (c as u32) < 256 {
+            escaped.push_str(&format!("%{:02X}", c as u32));
+        } else {
+            for unit in c.encode_utf16(&mut [0; 2]) {
+                escaped.push_str(&format!("%u{:04X}", unit));
+            }
+        }
+    }
+    escaped
+}
+
+fn unescape(escaped: &str) -> String {
+    let mut units = Vec::new();
+    let mut rest = escaped;
+    while let Some(c) = rest.chars().next() {
+        let parsed = if c != '%' {
+            None
+        } else if let Some(hex) = rest.strip_prefix("%u").and_then(|s| s.get(..4)) {
+            u16::from_str_radix(hex, 16).ok().map(|unit| (unit, 6))
+        } else {
+            rest.get(1..3)
+                .and_then(|hex| u16::from_str_radix(hex, 16).ok())
+                .map(|unit| (unit, 3))
+        };
+        match parsed {
+            Some((unit, len)) => {
+                units.push(unit);
+                rest = &rest[len..];
+            }
+            None => {
+                units.extend(c.encode_utf16(&mut [0; 2]).iter());
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+    String::from_utf16_lossy(&units)
+}
+
+struct Writer {
+    values: Vec<u8>,
+}
+impl Writer {
+    fn push(&mut self, mut value: u32, digits: usize) {
+        for _ in 0..digits {
+            self.values.push((value % 64) as u8);
+            value /= 64;
+        }
+    }
+}
+
+pub fn encode(pages: &[Page]) -> String {
+    let mut writer = Writer { values: Vec::new() };
+    let mut prev = [0; FIELD_CELLS];
+    let mut repeat_index: Option<usize> = None;
+
+    for (i, page) in pages.iter().enumerate() {
+        if page.field == prev {
+            match repeat_index {
+                Some(index) if writer.values[index] < 63 => writer.values[index] += 1,
+                _ => {
+                    writer.push(8 * FIELD_CELLS as u32 + FIELD_CELLS as u32 - 1, 2);
+                    repeat_index = Some(writer.values.len());
+                    writer.push(0, 1);
+                }
+            }
+        } else {
+            repeat_index = None;
+            let diffs: Vec<u32> = page
+                .field
+                .iter()
+                .zip(prev.iter())
+                .map(|(&cell, &prev_cell)| cell as u32 + 8 - prev_cell as u32)
+                .collect();
+            let mut start = 0;
+            while start < diffs.len() {
+                let diff = diffs[start];
+                let len = diffs[start..].iter().take_while(|&&d| d == diff).count();
+                writer.push(diff * FIELD_CELLS as u32 + len as u32 - 1, 2);
+                start += len;
+            }
+        }
+
+        let comment = page.comment.as_deref().map(escape);
+        let (kind, rotation, coordinate) = match page.piece {
+            Some(piece) => {
+                let [offset_x, offset_y] = piece.coordinate_offset();
+                let x = piece.x - offset_x;
+                let y = piece.y - offset_y;
+                let coordinate = (FIELD_HEIGHT as i32 - y - 1) * FIELD_WIDTH as i32 + x;
+                (piece.kind as u32, piece.rotation as u32, coordinate as u32)
+            }
+            None => (0, Rotation::Reverse as u32, 0),
+        };
+        let mut value = !page.lock as u32;
+        value = value * 2 + comment.is_some() as u32;
+        value = value * 2 + (i == 0) as u32; // guideline colors
+        value *= 2; // never mirrored
+        value *= 2; // never rises
+        value = value * FIELD_CELLS as u32 + coordinate;
+        value = value * 4 + rotation;
+        value = value * 8 + kind;
+        writer.push(value, 3);
+
+        if let Some(comment) = comment {
+            let indices: Vec<u32> = comment
+                .bytes()
+                .map(|b| COMMENT_TABLE.iter().position(|&c| c == b).unwrap_or(0) as u32)
+                .collect();
+            writer.push(indices.len().min(4095) as u32, 2);
+            for chunk in indices[..indices.len().min(4095)].chunks(4) {
+                let value = chunk
+                    .iter()
+                    .rev()
+                    .fold(0, |value, &index| value * COMMENT_BASE + index);
+                writer.push(value, 5);
+            }
+        }
+
+        prev = next_field(page, false, false);
+    }
+
+    let mut data = String::from(FUMEN_PREFIX);
+    for (i, &value) in writer.values.iter().enumerate() {
+        if i > 0 && i % LINE_LENGTH == 0 {
+            data.push('?');
+        }
+        data.push(ENCODE_TABLE[value as usize] as char);
+    }
+    data
+}
+
+struct Reader {
+    values: Vec<u32>,
+    index: usize,
+}
+impl Reader {
+    fn poll(&mut self, digits: usize) -> Option<u32> {
+        let mut value = 0;
+        for digit in 0..digits {
+            value += self.values.get(self.index)? * 64u32.pow(digit as u32);
+            self.index += 1;
+        }
+        Some(value)
+    }
+    fn is_empty(&self) -> bool {
+        self.index >= self.values.len()
+    }
+}
+
+// None when the data isn't a valid fumen
+pub fn decode(data: &str) -> Option<Vec<Page>> {
+    let data = data.trim().strip_prefix(FUMEN_PREFIX)?;
+    let values = data
+        .bytes()
+        .filter(|&b| b != b'?')
+        .map(|b| ENCODE_TABLE.iter().position(|&c| c == b).map(|v| v as u32))
+        .collect::<Option<Vec<u32>>>()?;
+    let mut reader = Reader { values, index: 0 };
+
+    let mut pages = Vec::new();
+    let mut prev = [0; FIELD_CELLS];
+    let mut repeat = 0;
+    let mut comment = None;
+
+    while !reader.is_empty() {
+        let mut field = prev;
+        if repeat > 0 {
+            repeat -= 1;
+        } else {
+            let mut index = 0;
+            while index < FIELD_CELLS {
+                let value = reader.poll(2)?;
+                let diff = value / FIELD_CELLS as u32;
+                let len = (value % FIELD_CELLS as u32 + 1) as usize;
+                if diff == 8 && len == FIELD_CELLS && index == 0 {
+                    repeat = reader.poll(1)?;
+                }
+                for cell in field.get_mut(index..index + len)? {
+                    *cell = (*cell as u32 + diff).checked_sub(8)? as u8;
+                }
+                index += len;
+            }
+        }
+
+        let mut value = reader.poll(3)?;
+        let kind = value % 8;
+        value /= 8;
+        let rotation = Rotation::ALL[(value % 4) as usize];
+        value /= 4;
+        let coordinate = (value % FIELD_CELLS as u32) as i32;
+        value /= FIELD_CELLS as u32;
+        let rise = value % 2 == 1;
+        value /= 2;
+        let mirror = value % 2 == 1;
+        value /= 2;
+        value /= 2; // colors
+        let has_comment = value % 2 == 1;
+        value /= 2;
+        let lock = value % 2 == 0;
+
+        if has_comment {
+            let len = reader.poll(2)? as usize;
+            let mut escaped = Vec::with_capacity(len);
+            while escaped.len() < len {
+                let mut value = reader.poll(5)?;
+                for _ in 0..4 {
+                    escaped.push(*COMMENT_TABLE.get((value % COMMENT_BASE) as usize)?);
+                    value /= COMMENT_BASE;
+                }
+            }
+            escaped.truncate(len);
+            comment = Some(unescape(&String::from_utf8_lossy(&escaped)));
+        }
+
+        let piece = (1..=7).contains(&kind).then(|| {
+            let mut piece = Piece {
+                kind: kind as u8,
+                rotation,
+                x: coordinate % FIELD_WIDTH as i32,
+                y: FIELD_HEIGHT as i32 - 1 - coordinate / FIELD_WIDTH as i32,
+            };
+            let [offset_x, offset_y] = piece.coordinate_offset();
+            piece.x += offset_x;
+            piece.y += offset_y;
+            piece
+        });
+
+        let page = Page {
+            field,
+            piece,
+            lock,
+            comment: comment.clone(),
+        };
+        prev = next_field(&page, rise, mirror);
+        pages.push(page);
+    }
+
+    Some(pages)
+}
+
+// every placement of the current game, used to export the game as a multi page fumen
+#[derive(Resource, Default)]
+pub struct Replay {
+    pub pages: Vec<Page>,
+}
+
+pub fn fumen_type(sprite: &Sprite, garbage: bool) -> u8 {
+    if garbage {
+        return FUMEN_GARBAGE;
+    }
+    sprite
+        .rect
+        .and_then(|rect| BLOCK_RECT_START.iter().position(|&start| start == rect.min))
+        .map_or(FUMEN_GARBAGE, |index| FUMEN_TYPES[index])
+}
+
+pub fn tetris_index(kind: u8) -> Option<usize> {
+    FUMEN_TYPES.iter().position(|&t| t == kind)
+}
+
+fn board_field(
+    block_q: &Query<(&Transform, &Sprite, Has<Garbage>), With<Block>>,
+    field_children: &Children,
+) -> Field {
+    let mut field = [0; FIELD_CELLS];
+    for (transform, sprite, garbage) in block_q.iter_many(field_children) {
+        let (x, y) = cell_index(STANDARD_SIZE, transform.translation.truncate());
+        if let Some(index) = field_index(x, y) {
+            field[index] = fumen_type(sprite, garbage);
+        }
+    }
+    field
+}
+
+fn active_piece(
+    active_tetris_q: &Query<(&ActiveTetris, &Transform, &Children, &Visibility)>,
+    transform_q: &Query<&Transform, Without<ActiveTetris>>,
+    field_children: &Children,
+) -> Option<Piece> {
+    // the active tetris is hidden while editing and between placements
+    let (active_tetris, tetris_transform, children, visibility) =
+        active_tetris_q.iter_many(field_children).next()?;
+    if visibility == Visibility::Hidden {
+        return None;
+    }
+    let mut cells = [[0; 2]; 4];
+    for (cell, &child) in cells.iter_mut().zip(children.iter()) {
+        let point = tetris_transform.transform_point(transform_q.get(child).ok()?.translation);
+        let (x, y) = cell_index(STANDARD_SIZE, point.truncate());
+        *cell = [x, y];
+    }
+    Piece::from_cells(FUMEN_TYPES[active_tetris.index], &cells)
+}
+
+pub fn setup(mut commands: Commands) {
+    commands.insert_resource(Replay::default());
+}
+
+// runs right before the active tetris gets placed
+pub fn record(
+    mut replay: ResMut<Replay>,
+    rules: Res<Rules>,
+    board_q: Query<(&Board, &Stats, &GameState, &Children), With<MainBoard>>,
+    block_q: Query<(&Transform, &Sprite, Has<Garbage>), With<Block>>,
+    active_tetris_q: Query<(&ActiveTetris, &Transform, &Children, &Visibility)>,
+    transform_q: Query<&Transform, Without<ActiveTetris>>,
+) {
+    let (board, stats, game_state, field_children) = board_q.single();
+    // fumen only knows standard fields and the tetrises
+    if *game_state != GameState::Place
+        || board.size != STANDARD_SIZE
+        || !rules.pieces.is_standard()
+        || rules.big
+    {
+        return;
+    }
+    // undoing takes back placements, so only keep the ones that happened before this one
+    replay.pages.truncate(stats.pieces as usize);
+    replay.pages.push(Page {
+        field: board_field(&block_q, field_children),
+        piece: active_piece(&active_tetris_q, &transform_q, field_children),
+        lock: true,
+        comment: None,
+    });
+}
+
+pub fn export(
+    button_input: Res<ButtonInput<KeyCode>>,
+    replay: Res<Replay>,
+    rules: Res<Rules>,
+    board_q: Query<(&Board, &Children), With<MainBoard>>,
+    block_q: Query<(&Transform, &Sprite, Has<Garbage>), With<Block>>,
+    active_tetris_q: Query<(&ActiveTetris, &Transform, &Children, &Visibility)>,
+    transform_q: Query<&Transform, Without<ActiveTetris>>,
+) {
+    let (path, data) = if button_input.just_pressed(KeyCode::F1) {
+        let (board, field_children) = board_q.single();
+        if board.size != STANDARD_SIZE || !rules.pieces.is_standard() || rules.big {
+            warn!("Fumen only has standard fields and the tetrises");
+            return;
+        }
+        let page = Page {
+            field: board_field(&block_q, field_children),
+            piece: active_piece(&active_tetris_q, &transform_q, field_children),
+            lock: false,
+            comment: None,
+        };
+        (BOARD_PATH, encode(&[page]))
+    } else if button_input.just_pressed(KeyCode::F2) && !replay.pages.is_empty() {
+        (REPLAY_PATH, encode(&replay.pages))
+    } else {
+        return;
+    };
+
+    info!("{}", data);
+    match fs::write(path, data) {
+        Ok(()) => info!("Saved fumen to {}", path),
+        Err(err) => error!("Failed to save fumen: {}", err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a single page of an empty field with every piece and rotation around the cell (4, 2),
+    // encoded by hand from the v115 format
+    #[rustfmt::skip]
+    const PIECES: [(&str, u8, Rotation, [[i32; 2]; 4]); 28] = [
+        ("v115@vhARGJ", 1, Rotation::Spawn, [[3, 2], [4, 2], [5, 2], [6, 2]]),
+        ("v115@vhAJGJ", 1, Rotation::Right, [[4, 0], [4, 1], [4, 2], [4, 3]]),
+        ("v115@vhAhFJ", 1, Rotation::Reverse, [[2, 2], [3, 2], [4, 2], [5, 2]]),
+        ("v115@vhAZBJ", 1, Rotation::Left, [[4, 1], [4, 2], [4, 3], [4, 4]]),
+        ("v115@vhASGJ", 2, Rotation::Spawn, [[3, 2], [4, 2], [5, 2], [5, 3]]),
+        ("v115@vhAKGJ", 2, Rotation::Right, [[4, 1], [4, 2], [4, 3], [5, 1]]),
+        ("v115@vhACGJ", 2, Rotation::Reverse, [[3, 1], [3, 2], [4, 2], [5, 2]]),
+        ("v115@vhAaGJ", 2, Rotation::Left, [[3, 3], [4, 1], [4, 2], [4, 3]]),
+        ("v115@vhATBJ", 3, Rotation::Spawn, [[4, 2], [4, 3], [5, 2], [5, 3]]),
+        ("v115@vhALGJ", 3, Rotation::Right, [[4, 1], [4, 2], [5, 1], [5, 2]]),
+        ("v115@vhAjFJ", 3, Rotation::Reverse, [[3, 1], [3, 2], [4, 1], [4, 2]]),
+        ("v115@vhA7AJ", 3, Rotation::Left, [[3, 2], [3, 3], [4, 2], [4, 3]]),
+        ("v115@vhAUBJ", 4, Rotation::Spawn, [[3, 3], [4, 2], [4, 3], [5, 2]]),
+        ("v115@vhAMGJ", 4, Rotation::Right, [[4, 1], [4, 2], [5, 2], [5, 3]]),
+        ("v115@vhAEGJ", 4, Rotation::Reverse, [[3, 2], [4, 1], [4, 2], [5, 1]]),
+        ("v115@vhA8FJ", 4, Rotation::Left, [[3, 1], [3, 2], [4, 2], [4, 3]]),
+        ("v115@vhAVGJ", 5, Rotation::Spawn, [[3, 2], [4, 2], [4, 3], [5, 2]]),
+        ("v115@vhANGJ", 5, Rotation::Right, [[4, 1], [4, 2], [4, 3], [5, 2]]),
+        ("v115@vhAFGJ", 5, Rotation::Reverse, [[3, 2], [4, 1], [4, 2], [5, 2]]),
+        ("v115@vhAdGJ", 5, Rotation::Left, [[3, 2], [4, 1], [4, 2], [4, 3]]),
+        ("v115@vhAWGJ", 6, Rotation::Spawn, [[3, 2], [3, 3], [4, 2], [5, 2]]),
+        ("v115@vhAOGJ", 6, Rotation::Right, [[4, 1], [4, 2], [4, 3], [5, 3]]),
+        ("v115@vhAGGJ", 6, Rotation::Reverse, [[3, 2], [4, 2], [5, 1], [5, 2]]),
+        ("v115@vhAeGJ", 6, Rotation::Left, [[3, 1], [4, 1], [4, 2], [4, 3]]),
+        ("v115@vhAXBJ", 7, Rotation::Spawn, [[3, 2], [4, 2], [4, 3], [5, 3]]),
+        ("v115@vhAvGJ", 7, Rotation::Right, [[4, 2], [4, 3], [5, 1], [5, 2]]),
+        ("v115@vhAHGJ", 7, Rotation::Reverse, [[3, 1], [4, 1], [4, 2], [5, 2]]),
+        ("v115@vhAfGJ", 7, Rotation::Left, [[3, 2], [3, 3], [4, 1], [4, 2]]),
+    ];
+
+    #[test]
+    fn decode_empty() {
+        let pages = decode("v115@vhAAgH").unwrap();
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].field, [0; FIELD_CELLS]);
+        assert_eq!(pages[0].piece, None);
+        assert_eq!(pages[0].comment, None);
+        assert!(pages[0].lock);
+    }
+
+    #[test]
+    fn decode_pieces() {
+        for (data, kind, rotation, cells) in PIECES {
+            let pages = decode(data).unwrap();
+            let piece = pages[0].piece.unwrap();
+            assert_eq!((piece.kind, piece.rotation), (kind, rotation), "{}", data);
+            let mut piece_cells = piece.cells();
+            piece_cells.sort();
+            assert_eq!(piece_cells, cells, "{}", data);
+        }
+    }
+
+    #[test]
+    fn encode_pieces() {
+        for (data, kind, rotation, _) in PIECES {
+            let page = Page {
+                field: [0; FIELD_CELLS],
+                piece: Some(Piece {
+                    kind,
+                    rotation,
+                    x: 4,
+                    y: 2,
+                }),
+                lock: true,
+                comment: None,
+            };
+            assert_eq!(encode(&[page]), data);
+        }
+    }
+
+    #[test]
+    fn comment() {
+        let pages = decode("v115@vhAAgWBABBAAA").unwrap();
+        assert_eq!(pages[0].comment.as_deref(), Some("a"));
+        assert_eq!(encode(&pages), "v115@vhAAgWBABBAAA");
+    }
+
+    #[test]
+    fn mirror() {
+        // an I locked in the bottom left corner and mirrored into the bottom right one
+        let pages = decode("v115@vhAx+MvhAAAA").unwrap();
+        assert_eq!(pages.len(), 2);
+        assert_eq!(pages[0].comment, None);
+        for x in 0..FIELD_WIDTH as i32 {
+            let expected = if x >= 6 { FUMEN_TYPES[0] } else { 0 };
+            assert_eq!(pages[1].field[field_index(x, 0).unwrap()], expected);
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut field = [0; FIELD_CELLS];
+        for x in 0..FIELD_WIDTH as i32 - 1 {
+            field[field_index(x, -1).unwrap()] = FUMEN_GARBAGE;
+            field[field_index(x, 0).unwrap()] = FUMEN_TYPES[x as usize % 7];
+        }
+        let pages = vec![
+            Page {
+                field,
+                piece: Some(Piece {
+                    kind: 1,
+                    rotation: Rotation::Left,
+                    x: 9,
+                    y: 2,
+                }),
+                lock: true,
+                comment: Some("tetris, 100% ✓".to_string()),
+            },
+            Page {
+                field,
+                piece: None,
+                lock: false,
+                comment: None,
+            },
+        ];
+        let decoded = decode(&encode(&pages)).unwrap();
+        assert_eq!(decoded.len(), 2);
+        assert_eq!(decoded[0].field, pages[0].field);
+        assert_eq!(decoded[0].piece, pages[0].piece);
+        assert_eq!(decoded[0].comment, pages[0].comment);
+        // the comment carries over to the pages after it
+        assert_eq!(decoded[1].comment, pages[0].comment);
+        assert_eq!(decoded[1].field, pages[1].field);
+        assert!(!decoded[1].lock);
+    }
+}
@@ -0,0 +1,358 @@
+use bevy::ecs::system::SystemParam;
+use bevy::math::vec2;
+use bevy::prelude::*;
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::field::*;
+use crate::pieces::*;
+use crate::rules::*;
+use crate::tetris::*;
+use crate::*;
+
+// the cells of a piece in one of its orientations, placed at its spawn position
+fn shape(piece: &Piece, orientation: u32) -> Vec<(i32, i32)> {
+    let center = piece.spawn + vec2(GRID_WIDTH as f32 * 0.5 - 0.5, GRID_HEIGHT as f32 - 0.5);
+    piece
+        .cells
+        .iter()
+        .map(|&position| {
+            let mut position = position;
+            for _ in 0..orientation {
+                position = vec2(position.y, -position.x);
+            }
+            let cell = (center + position).round();
+            (cell.x as i32, cell.y as i32)
+        })
+        .collect()
+}
+
+fn next_orientation(piece: &Piece, orientation: u32, rules: &Rules) -> u32 {
+    (orientation + 1) % piece.orientations(rules)
+}
+
+// where a tetris is, the offset is in cells from where it spawns in the middle of a standard field,
+// so the same spot has the same cells on fields of any size
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Spot {
+    pub index: usize,
+    pub orientation: u32,
+    pub x: i32,
+    pub y: i32,
+    // the tetris comes from holding instead of being the current one
+    pub hold: bool,
+}
+impl Spot {
+    pub fn cells(&self, rules: &Rules) -> Vec<(i32, i32)> {
+        shape(&rules.pieces[self.index], self.orientation)
+            .into_iter()
+            .map(|(x, y)| (x + self.x, y + self.y))
+            .collect()
+    }
+    // the spot of the active tetris on the field of the board
+    pub fn from_active(
+        active_tetris: &ActiveTetris,
+        transform: &Transform,
+        board: &Board,
+        rules: &Rules,
+    ) -> Self {
+        let piece = &rules.pieces[active_tetris.index];
+        let offset = transform.translation.truncate() / board.cell_size() - piece.spawn
+            + size_offset(board.play_size());
+        Self {
+            index: active_tetris.index,
+            orientation: active_tetris.rotation_index as u32 % piece.orientations(rules),
+            x: offset.x.round() as i32,
+            y: offset.y.round() as i32,
+            hold: false,
+        }
+    }
+    pub fn moved(&self, x: i32, y: i32) -> Self {
+        Self {
+            x: self.x + x,
+            y: self.y + y,
+            ..*self
+        }
+    }
+    pub fn transform(&self, board: &Board, rules: &Rules) -> Transform {
+        let offset = vec2(self.x as f32, self.y as f32) - size_offset(board.play_size());
+        let mut transform = Transform::from_translation(
+            ((rules.pieces[self.index].spawn + offset) * board.cell_size()).extend(0.0),
+        )
+        .with_scale(board.tetris_scale());
+        transform.rotate_z((-90.0 * self.orientation as f32).to_radians());
+        transform
+    }
+}
+
+// columns the middle of a field is right of the middle of a standard one
+// and rows its top is above the top of a standard one
+fn size_offset(size: UVec2) -> Vec2 {
+    vec2(
+        (size.x as f32 - GRID_WIDTH as f32) * 0.5,
+        size.y as f32 - GRID_HEIGHT as f32,
+    )
+}
+
+// the field of a board as one bitmask per row, from the bottom up, in big mode a bit is a big cell
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Grid {
+    pub width: u32,
+    // the spot the tetrises of the board spawn at
+    pub spawn_x: i32,
+    pub spawn_y: i32,
+    pub rows: Vec<u64>,
+}
+impl Grid {
+    pub fn new(board: &Board) -> Self {
+        let play_size = board.play_size();
+        Self {
+            width: play_size.x,
+            spawn_x: board.spawn_column + play_size.x.div_ceil(2) as i32 - GRID_WIDTH as i32 / 2,
+            spawn_y: play_size.y as i32 - GRID_HEIGHT as i32,
+            rows: Vec::new(),
+        }
+    }
+    // a big cell counts as filled as soon as any of its blocks is
+    pub fn from_blocks<'a>(board: &Board, transforms: impl Iterator<Item = &'a Transform>) -> Self {
+        let mut grid = Self::new(board);
+        grid.rows = vec![0; board.play_size().y as usize];
+        for transform in transforms {
+            if let Some((column, row)) = cell_at(board.size, transform.translation.truncate()) {
+                grid.rows[(row / board.cell_blocks) as usize] |= 1 << (column / board.cell_blocks);
+            }
+        }
+        grid.trim();
+        grid
+    }
+    pub fn spawn(&self, index: usize) -> Spot {
+        Spot {
+            index,
+            orientation: 0,
+            x: self.spawn_x,
+            y: self.spawn_y,
+            hold: false,
+        }
+    }
+    pub fn full_row(&self) -> u64 {
+        (1 << self.width) - 1
+    }
+    pub fn is_filled(&self, x: i32, y: i32) -> bool {
+        if x < 0 || x >= self.width as i32 || y < 0 {
+            return true;
+        }
+        self.rows
+            .get(y as usize)
+            .is_some_and(|row| row & (1 << x) != 0)
+    }
+    pub fn fits(&self, step: &Spot, rules: &Rules) -> bool {
+        step.cells(rules)
+            .iter()
+            .all(|&(x, y)| !self.is_filled(x, y))
+    }
+    pub fn height(&self) -> u32 {
+        self.rows
+            .iter()
+            .rposition(|&row| row != 0)
+            .map_or(0, |y| y as u32 + 1)
+    }
+    // drops the empty rows at the top so equal fields compare equal
+    pub fn trim(&mut self) {
+        while self.rows.last() == Some(&0) {
+            self.rows.pop();
+        }
+    }
+    pub fn filled(&self) -> u32 {
+        self.rows.iter().map(|row| row.count_ones()).sum()
+    }
+    // places the tetris and returns how many lines it cleared
+    pub fn place(&mut self, spot: &Spot, rules: &Rules) -> u32 {
+        self.place_cells(&spot.cells(rules))
+    }
+    pub fn place_cells(&mut self, cells: &[(i32, i32)]) -> u32 {
+        for &(x, y) in cells {
+            if x < 0 || x >= self.width as i32 || y < 0 {
+                continue;
+            }
+            if self.rows.len() <= y as usize {
+                self.rows.resize(y as usize + 1, 0);
+            }
+            self.rows[y as usize] |= 1 << x;
+        }
+        let len = self.rows.len();
+        let full_row = self.full_row();
+        self.rows.retain(|&row| row != full_row);
+        let cleared = (len - self.rows.len()) as u32;
+        self.trim();
+        cleared
+    }
+    // every region of empty cells below the height has to be filled by whole pieces,
+    // so its size has to be a multiple of the unit
+    pub fn can_fill(&self, height: u32, unit: u32) -> bool {
+        let mut visited = vec![0u64; height as usize];
+        for y in 0..height as i32 {
+            for x in 0..self.width as i32 {
+                if self.is_filled(x, y) || visited[y as usize] & (1 << x) != 0 {
+                    continue;
+                }
+                let mut size = 0u32;
+                let mut stack = vec![(x, y)];
+                visited[y as usize] |= 1 << x;
+                while let Some((x, y)) = stack.pop() {
+                    size += 1;
+                    for (x, y) in [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)] {
+                        if y >= height as i32
+                            || self.is_filled(x, y)
+                            || visited[y as usize] & (1 << x) != 0
+                        {
+                            continue;
+                        }
+                        visited[y as usize] |= 1 << x;
+                        stack.push((x, y));
+                    }
+                }
+                if !size.is_multiple_of(unit) {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+    // everything above the stack is empty, so starting right above it reaches the same spots
+    pub fn above_stack(&self, spawn: Spot, rules: &Rules) -> Spot {
+        let bottom = spawn.cells(rules).iter().map(|&(_, y)| y).min().unwrap();
+        Spot {
+            y: (self.height() as i32 - bottom).min(spawn.y),
+            ..spawn
+        }
+    }
+    // where the tetris lands when dropped
+    pub fn drop(&self, spot: Spot, rules: &Rules) -> Spot {
+        let mut spot = spot;
+        while self.fits(&spot.moved(0, -1), rules) {
+            spot = spot.moved(0, -1);
+        }
+        spot
+    }
+    // every spot the tetris can be moved to from its spawn position and placed at
+    pub fn placements(&self, index: usize, rules: &Rules) -> Vec<Spot> {
+        let spawn = self.spawn(index);
+        if !self.fits(&spawn, rules) {
+            return Vec::new();
+        }
+        self.reach(self.above_stack(spawn, rules), rules)
+            .into_iter()
+            .map(|(spot, _)| spot)
+            .collect()
+    }
+    // every spot the tetris can be placed at from the start, with the spots on the way there
+    pub fn reach(&self, start: Spot, rules: &Rules) -> Vec<(Spot, Vec<Spot>)> {
+        if !self.fits(&start, rules) {
+            return Vec::new();
+        }
+
+        let mut parents = HashMap::from([(start, start)]);
+        let mut queue = VecDeque::from([start]);
+        let mut placements = Vec::new();
+        let mut placed_cells = HashSet::new();
+
+        while let Some(spot) = queue.pop_front() {
+            for next in self.moves(&spot, rules) {
+                if self.fits(&next, rules) && !parents.contains_key(&next) {
+                    parents.insert(next, spot);
+                    queue.push_back(next);
+                }
+            }
+
+            if self.fits(&spot.moved(0, -1), rules) {
+                continue;
+            }
+            let mut cells = spot.cells(rules);
+            cells.sort();
+            if !placed_cells.insert(cells) {
+                continue;
+            }
+            let mut path = vec![spot];
+            while path[0] != start {
+                path.insert(0, parents[&path[0]]);
+            }
+            placements.push((spot, path));
+        }
+        placements
+    }
+    // the spots a single slide, soft drop or rotation leads to
+    fn moves(&self, spot: &Spot, rules: &Rules) -> Vec<Spot> {
+        let mut moves = vec![spot.moved(-1, 0), spot.moved(1, 0), spot.moved(0, -1)];
+        moves.extend(self.rotated(spot, rules));
+        moves
+    }
+    // the spot after a rotation, with the same tests the rotation does
+    pub fn rotated(&self, spot: &Spot, rules: &Rules) -> Option<Spot> {
+        let piece = &rules.pieces[spot.index];
+        if piece.fixed {
+            return None;
+        }
+        let rotated = Spot {
+            orientation: next_orientation(piece, spot.orientation, rules),
+            ..*spot
+        };
+        let kicks: &[i32] = if rules.kicks { &piece.kicks } else { &[] };
+        [0].iter()
+            .chain(kicks)
+            .map(|&test| rotated.moved(test, 0))
+            .find(|kicked| self.fits(kicked, rules))
+    }
+}
+
+// a tinted tetris at the spot, drawn under the active one
+pub fn spawn_spot(
+    commands: &mut Commands,
+    sprite_handle: &Res<SpriteHandle>,
+    field: Entity,
+    board: &Board,
+    spot: &Spot,
+    rules: &Rules,
+    tint: Color,
+) -> Entity {
+    let tetris = spawn_tetris(commands, &rules.pieces[spot.index], sprite_handle, tint);
+    let mut transform = spot.transform(board, rules);
+    transform.translation.z = -0.5;
+    commands.entity(tetris).insert(transform).set_parent(field);
+    tetris
+}
+
+// the tetrises and blocks of a board, found through the children of its field
+#[derive(SystemParam)]
+pub struct BoardView<'w, 's> {
+    active_tetris_q: Query<'w, 's, (&'static ActiveTetris, &'static Transform)>,
+    hold_field_q: Query<'w, 's, &'static Children, With<HoldField>>,
+    hold_tetris_q: Query<'w, 's, (&'static Visibility, &'static HoldTetris)>,
+    block_q: Query<'w, 's, (&'static Transform, &'static Sprite, Has<Garbage>), With<Block>>,
+    children_q: Query<'w, 's, &'static Children, With<Board>>,
+}
+impl BoardView<'_, '_> {
+    pub fn active(&self, field_children: &Children) -> Option<(&ActiveTetris, &Transform)> {
+        self.active_tetris_q.iter_many(field_children).next()
+    }
+    // None when the rules have no hold, otherwise the held tetris if there is one
+    pub fn hold(&self, field_children: &Children) -> Option<Option<usize>> {
+        let hold_field_children = self.hold_field_q.iter_many(field_children).next()?;
+        let (visibility, hold_tetris) = self.hold_tetris_q.iter_many(hold_field_children).next()?;
+        Some(matches!(visibility, Visibility::Visible).then_some(hold_tetris.index))
+    }
+    pub fn blocks<'a>(
+        &'a self,
+        field_children: &'a Children,
+    ) -> impl Iterator<Item = (&'a Transform, &'a Sprite, bool)> + 'a {
+        self.block_q.iter_many(field_children)
+    }
+    // the placed blocks of the field the board plays in, shared in co-op
+    pub fn grid(&self, board: &Board, field_children: &Children) -> Grid {
+        let placed_children = placed_children(board, field_children, &self.children_q);
+        Grid::from_blocks(
+            board,
+            self.blocks(placed_children)
+                .map(|(transform, _, _)| transform),
+        )
+    }
+}
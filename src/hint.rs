@@ -0,0 +1,115 @@
+use bevy::prelude::*;
+
+use crate::bot::*;
+use crate::field::*;
+use crate::grid::*;
+use crate::mode::*;
+use crate::rules::*;
+use crate::*;
+
+pub const HINT_KEY: KeyCode = KeyCode::KeyG;
+
+// where the evaluator would place the active tetris, only practice modes have it
+#[derive(Resource, Default)]
+pub struct PlacementHint {
+    pub enabled: bool,
+    // the placed tetris count and active tetris the spot was found for
+    pub key: Option<(u32, usize)>,
+    pub spot: Option<Spot>,
+}
+
+#[derive(Component)]
+pub struct HintTetris;
+
+pub fn setup(mut commands: Commands, mode: Res<GameMode>) {
+    if matches!(*mode, GameMode::Zen { .. } | GameMode::Editor) {
+        commands.insert_resource(PlacementHint::default());
+    }
+}
+
+pub fn toggle(button_input: Res<ButtonInput<KeyCode>>, mut hint: ResMut<PlacementHint>) {
+    if button_input.just_pressed(HINT_KEY) {
+        hint.enabled = !hint.enabled;
+        hint.key = None;
+        hint.spot = None;
+    }
+}
+
+pub fn update(
+    mut hint: ResMut<PlacementHint>,
+    rules: Res<Rules>,
+    board_q: Query<(&Board, &Stats, &Children), With<MainBoard>>,
+    view: BoardView,
+) {
+    if !hint.enabled {
+        return;
+    }
+    let (board, stats, field_children) = board_q.single();
+    let Some((active_tetris, _)) = view.active(field_children) else {
+        return;
+    };
+    let index = active_tetris.index;
+    let key = Some((stats.pieces, index));
+    if hint.key == key {
+        return;
+    }
+
+    hint.key = key;
+    hint.spot = best_spot(&view.grid(board, field_children), index, &rules);
+}
+
+// runs right before the active tetris gets placed
+pub fn check(
+    hint: Res<PlacementHint>,
+    rules: Res<Rules>,
+    mut board_q: Query<(&Board, &mut Stats, &Children), With<MainBoard>>,
+    view: BoardView,
+) {
+    let Some(spot) = hint.spot.filter(|_| hint.enabled) else {
+        return;
+    };
+    let (board, mut stats, field_children) = board_q.single_mut();
+    let Some((active_tetris, transform)) = view.active(field_children) else {
+        return;
+    };
+    let mut placed = Spot::from_active(active_tetris, transform, board, &rules).cells(&rules);
+    let mut hinted = spot.cells(&rules);
+    placed.sort();
+    hinted.sort();
+
+    stats.hinted += 1;
+    if placed == hinted {
+        stats.hint_matches += 1;
+    }
+}
+
+pub fn update_overlay(
+    mut commands: Commands,
+    hint: Res<PlacementHint>,
+    sprite_handle: Res<SpriteHandle>,
+    rules: Res<Rules>,
+    board_q: Query<(Entity, &Board), With<MainBoard>>,
+    hint_tetris_q: Query<Entity, With<HintTetris>>,
+) {
+    if !hint.is_changed() {
+        return;
+    }
+    for entity in hint_tetris_q.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    let Some(spot) = hint.spot.filter(|_| hint.enabled) else {
+        return;
+    };
+    let (field, board) = board_q.single();
+    let tetris = spawn_spot(
+        &mut commands,
+        &sprite_handle,
+        field,
+        board,
+        &spot,
+        &rules,
+        Color::rgba(0.5, 1.0, 0.5, 0.4),
+    );
+    commands.entity(tetris).insert(HintTetris);
+}
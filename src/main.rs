@@ -1,28 +1,58 @@
+mod attack;
+mod bot;
+mod classic;
+mod controls;
+mod coop;
+mod editor;
 mod field;
+mod finesse;
+mod fumen;
+mod grid;
+mod hint;
+mod master;
+mod mode;
+mod net;
+mod pc;
+mod pieces;
+mod practice;
+mod rollback;
+mod royale;
+mod rules;
+mod tbp;
 mod tetris;
+mod versus;
 
+use std::collections::VecDeque;
 use std::mem;
+use std::time::Duration;
 
-use bevy::{core::*, prelude::*, render::camera::*, window::*};
+use bevy::{core::*, ecs::schedule::ScheduleLabel, prelude::*, render::camera::*, window::*};
 
 const SPRITES_PATH: &str = "sprites.png";
 const FONT_PATH: &str = "retro_gaming.ttf";
 const VISIBLE_FRAME: u32 = 5;
 const FALL_TIME: f32 = 0.5;
 const FAST_FALL_TIME: f32 = 1.0 / 15.0;
+const LOCK_TIME: f32 = 0.5;
 const SLIDE_START_TIME: f32 = 0.2;
 const SLIDE_TIME: f32 = 1.0 / 20.0;
+pub const MAX_PREVIEW_COUNT: usize = 6;
 
-fn game_state_setup(mut commands: Commands) {
-    commands.insert_resource(GameState::Play);
-    commands.insert_resource(TetrisManager::new());
-}
-fn camera_setup(mut commands: Commands) {
+fn camera_setup(mut commands: Commands, mode: Res<mode::GameMode>, size: Res<field::FieldSize>) {
     commands.spawn(Camera2dBundle {
         projection: OrthographicProjection {
             near: -10.0,
             far: 10.0,
-            scaling_mode: ScalingMode::FixedVertical(field::FIELD_RECT.height()),
+            // boards are side by side, so more players and wider fields make the view wider
+            // and taller fields make it taller
+            scaling_mode: ScalingMode::AutoMin {
+                min_width: match *mode {
+                    mode::GameMode::Royale { .. } => royale::VIEW_WIDTH,
+                    mode::GameMode::Coop { .. } => coop::view_width(size.0),
+                    _ => field::board_spacing(size.0) * mode.players() as f32,
+                },
+                min_height: field::field_size(size.0).y,
+            },
             ..Default::default()
         },
         ..Default::default()
@@ -47,84 +77,201 @@ fn make_visible(frame: Res<FrameCount>, mut window_q: Query<&mut Window>) {
 pub struct SpriteHandle(pub Handle<Image>);
 #[derive(Resource)]
 pub struct FontHandle(pub Handle<Font>);
-#[derive(Resource)]
+// the state of a single board, kept on its field entity
+#[derive(Component, Clone)]
 pub struct TetrisManager {
-    pub order: [usize; 7],
-    pub next_order: [usize; 7],
-    pub order_index: usize,
+    pub queue: VecDeque<usize>,
+    pub randomizer: rules::Randomizer,
+    pub piece_count: usize,
+    // every board draws from its own generator, so seeded boards play out the same everywhere
+    pub rng: fastrand::Rng,
+    pub preview_count: usize,
     pub fall_timer: Timer,
     pub fast_fall_timer: Timer,
+    // rows fallen every time the fall timer finishes, at least the field height means 20G
+    pub fall_rows: u32,
+    pub lock_timer: Timer,
+    // spawn delay after every placement and extra delay after clearing lines
+    pub spawn_delay: Duration,
+    pub line_clear_delay: Duration,
+    pub delay_timer: Timer,
     pub slide_start_timer: Timer,
     pub slide_timer: Timer,
     pub slide_dir: f32,
     pub hit_floor: bool,
     pub hold: bool,
+    // slides and rotations used on the active tetris
+    pub inputs: u32,
+    // the last move of the active tetris was a rotation, needed to tell t-spins apart
+    pub rotated: bool,
 }
 impl TetrisManager {
-    fn new() -> Self {
-        let mut order = [0; 7];
-        let mut next_order = [0; 7];
-
-        for i in 0..7 {
-            order[i] = i;
-            next_order[i] = i;
-        }
-        fastrand::shuffle(&mut order);
-        fastrand::shuffle(&mut next_order);
-
-        Self {
-            order,
-            next_order,
-            order_index: 0,
+    pub fn new(rules: &rules::Rules) -> Self {
+        let mut manager = Self {
+            queue: VecDeque::with_capacity(MAX_PREVIEW_COUNT + 8),
+            randomizer: rules.randomizer,
+            piece_count: rules.pieces.count(),
+            rng: fastrand::Rng::new(),
+            preview_count: rules.preview_count.min(MAX_PREVIEW_COUNT),
             fall_timer: Timer::from_seconds(FALL_TIME, TimerMode::Repeating),
             fast_fall_timer: Timer::from_seconds(FAST_FALL_TIME, TimerMode::Repeating),
+            fall_rows: 1,
+            lock_timer: Timer::from_seconds(LOCK_TIME, TimerMode::Once),
+            spawn_delay: Duration::ZERO,
+            line_clear_delay: Duration::ZERO,
+            delay_timer: Timer::new(Duration::ZERO, TimerMode::Once),
             slide_start_timer: Timer::from_seconds(SLIDE_START_TIME, TimerMode::Once),
             slide_timer: Timer::from_seconds(SLIDE_TIME, TimerMode::Repeating),
             slide_dir: 0.0,
             hit_floor: false,
             hold: false,
+            inputs: 0,
+            rotated: false,
+        };
+        manager.fill_queue();
+        manager
+    }
+    // keeps at least the current tetris and every possible preview in the queue,
+    // so peeking never has to care where one bag ends and the next one starts
+    fn fill_queue(&mut self) {
+        while self.queue.len() <= MAX_PREVIEW_COUNT {
+            match self.randomizer {
+                rules::Randomizer::Bag => {
+                    let mut bag: Vec<usize> = (0..self.piece_count).collect();
+                    self.rng.shuffle(&mut bag);
+                    self.queue.extend(bag);
+                }
+                rules::Randomizer::NesReroll => {
+                    let mut index = self.rng.usize(0..=self.piece_count);
+                    if index == self.piece_count || self.queue.back() == Some(&index) {
+                        index = self.rng.usize(0..self.piece_count);
+                    }
+                    self.queue.push_back(index);
+                }
+            }
         }
     }
+    // starts the queue over from the seed
+    pub fn reseed(&mut self, seed: u64) {
+        self.rng = fastrand::Rng::with_seed(seed);
+        self.queue.clear();
+        self.fill_queue();
+    }
     pub fn current_tetris(&self) -> usize {
-        self.order[self.order_index]
+        self.queue[0]
     }
-    pub fn next_tetris(&self) -> usize {
-        if let Some(order) = self.order.get(self.order_index + 1) {
-            return *order;
-        }
-        self.next_order[(self.order_index + 1) % self.order.len()]
+    // index 0 is the tetris that comes right after the current one
+    pub fn next_tetris(&self, index: usize) -> usize {
+        self.queue[index + 1]
     }
     pub fn advance(&mut self) {
-        self.order_index += 1;
-        if self.order_index >= self.order.len() {
-            mem::swap(&mut self.order, &mut self.next_order);
-            fastrand::shuffle(&mut self.next_order);
-            self.order_index %= self.order.len();
-        }
+        self.queue.pop_front();
+        self.fill_queue();
+    }
+    // called whenever a new tetris starts falling
+    pub fn reset_timers(&mut self) {
+        self.fall_timer.reset();
+        self.fast_fall_timer.reset();
+        self.lock_timer.reset();
+        self.hit_floor = false;
+        self.slide_timer.reset();
+        self.slide_start_timer.reset();
+        self.slide_dir = 0.0;
+        self.inputs = 0;
+        self.rotated = false;
     }
 }
-#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
 enum GameState {
     Play,
     Place,
+    Delay,
     Advance,
     GameOver,
+    Finish,
+    Edit,
 }
 
-fn is_state_play(game_state: Res<GameState>) -> bool {
-    *game_state == GameState::Play
+// every board has its own state, the systems pick the boards they apply to themselves
+fn is_state_play(state_q: Query<&GameState>) -> bool {
+    state_q.iter().any(|state| *state == GameState::Play)
+}
+fn is_state_place(state_q: Query<&GameState>) -> bool {
+    state_q.iter().any(|state| *state == GameState::Place)
 }
-fn is_state_place(game_state: Res<GameState>) -> bool {
-    *game_state == GameState::Place
+fn is_state_delay(state_q: Query<&GameState>) -> bool {
+    state_q.iter().any(|state| *state == GameState::Delay)
 }
-fn is_state_advance(game_state: Res<GameState>) -> bool {
-    *game_state == GameState::Advance
+fn is_state_advance(state_q: Query<&GameState>) -> bool {
+    state_q.iter().any(|state| *state == GameState::Advance)
 }
-fn is_state_game_over(game_state: Res<GameState>) -> bool {
-    *game_state == GameState::GameOver
+fn is_state_running(state_q: Query<&GameState>) -> bool {
+    state_q
+        .iter()
+        .any(|state| !matches!(state, GameState::GameOver | GameState::Finish))
 }
+fn is_state_edit(state_q: Query<&GameState>) -> bool {
+    state_q.iter().any(|state| *state == GameState::Edit)
+}
+fn is_state_game_over(state_q: Query<&GameState>) -> bool {
+    state_q.iter().any(|state| *state == GameState::GameOver)
+}
+
+// the systems that play the boards, once every frame and again for every tick an online game
+// goes back to
+#[derive(ScheduleLabel, Debug, Clone, PartialEq, Eq, Hash)]
+struct GameTick;
 
 fn main() {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    // the field size, the piece file, big mode and the next queue come before the mode, the rest of
+    // the arguments stay where the modes expect them
+    let mut size_arg = None;
+    let mut pieces_arg = None;
+    let mut big_arg = false;
+    let mut next_arg = None;
+    loop {
+        let taken = match args.first().map(String::as_str) {
+            Some(field::SIZE_ARG) => {
+                size_arg = args.get(1).and_then(|arg| field::parse_size(arg));
+                2
+            }
+            Some(pieces::PIECES_ARG) => {
+                pieces_arg = args.get(1).cloned();
+                2
+            }
+            Some(rules::BIG_ARG) => {
+                big_arg = true;
+                1
+            }
+            Some(rules::NEXT_ARG) => {
+                next_arg = args
+                    .get(1)
+                    .and_then(|arg| arg.parse::<usize>().ok())
+                    .map(|count| count.min(MAX_PREVIEW_COUNT));
+                2
+            }
+            _ => break,
+        };
+        args.drain(..args.len().min(taken));
+    }
+    let bot_arg = args
+        .iter()
+        .position(|arg| arg == tbp::BOT_ARG)
+        .unwrap_or(args.len());
+    let mode = mode::GameMode::from_args(args[..bot_arg].iter().cloned());
+    // the editor setups and the online protocol only know cells of a single block
+    let mode_rules = mode.rules();
+    let rules = rules::Rules {
+        big: big_arg && !matches!(mode, mode::GameMode::Editor | mode::GameMode::Online),
+        preview_count: next_arg.unwrap_or(mode_rules.preview_count),
+        ..mode_rules
+    };
+    let mut size = mode.field_size(size_arg);
+    if rules.big {
+        size = field::big_size(size);
+    }
+
     App::new()
         .add_plugins(
             DefaultPlugins
@@ -142,30 +289,153 @@ fn main() {
         )
         .insert_resource(Msaa::Off)
         .insert_resource(ClearColor(Color::BLACK))
+        .insert_resource(mode)
+        .insert_resource(field::FieldSize(size))
+        .insert_resource(rules)
+        .insert_resource(pieces::PiecesPath(pieces_arg))
+        .insert_resource(editor::SetupArg(args[..bot_arg].get(1).cloned()))
+        .insert_resource(net::NetCommand(
+            args.get(1..bot_arg).unwrap_or_default().to_vec(),
+        ))
+        .add_event::<versus::Attack>()
+        .insert_resource(tbp::BotCommand(
+            args.iter().skip(bot_arg + 1).cloned().collect(),
+        ))
         .add_systems(
             Startup,
             (
-                (asset_setup, camera_setup, game_state_setup),
+                (asset_setup, camera_setup, pieces::setup),
                 field::setup,
+                mode::setup,
+                practice::setup,
+                hint::setup,
+                finesse::setup,
+                fumen::setup,
+                pc::setup,
+                bot::setup,
+                tbp::setup,
+                net::setup,
                 tetris::setup,
+                mode::setup_garbage,
+                versus::setup,
+                royale::setup,
+                editor::setup,
                 field::load_score,
             )
                 .chain(),
         )
+        .add_systems(
+            GameTick,
+            (
+                (
+                    mode::tick_timer
+                        .run_if(is_state_running)
+                        .run_if(not(is_state_edit)),
+                    mode::check_time_limit.run_if(is_state_running),
+                    mode::rise_garbage.run_if(is_state_play),
+                )
+                    .chain(),
+                (royale::retarget, royale::play_bots)
+                    .chain()
+                    .run_if(resource_exists::<royale::Royale>),
+                (
+                    tetris::hold.run_if(is_state_play),
+                    tetris::rotate.run_if(is_state_play),
+                    tetris::slide.run_if(is_state_play),
+                    tetris::fall.run_if(is_state_play),
+                )
+                    .chain(),
+                (
+                    practice::record
+                        .run_if(is_state_place)
+                        .run_if(resource_exists::<practice::History>),
+                    fumen::record.run_if(is_state_place),
+                    hint::check
+                        .run_if(is_state_place)
+                        .run_if(resource_exists::<hint::PlacementHint>),
+                    finesse::check.run_if(is_state_place),
+                    (
+                        versus::send.run_if(is_state_place),
+                        versus::deliver.run_if(not(resource_exists::<royale::Royale>)),
+                        royale::deliver.run_if(resource_exists::<royale::Royale>),
+                    )
+                        .chain()
+                        .run_if(resource_exists::<attack::AttackTable>),
+                    tetris::place.run_if(is_state_place),
+                    tetris::clear_block.run_if(is_state_place),
+                    tetris::delay.run_if(is_state_delay),
+                )
+                    .chain(),
+                (
+                    mode::check_goal.run_if(is_state_advance),
+                    finesse::next_target
+                        .run_if(is_state_advance)
+                        .run_if(resource_exists::<finesse::Trainer>),
+                    versus::receive.run_if(is_state_advance),
+                    tetris::advance.run_if(is_state_advance),
+                    tetris::check_advanced_block.run_if(is_state_advance),
+                    coop::top_out.run_if(is_state_game_over),
+                    versus::finish
+                        .run_if(is_state_game_over)
+                        .run_if(not(resource_exists::<royale::Royale>)),
+                    royale::knock_out
+                        .run_if(is_state_game_over)
+                        .run_if(resource_exists::<royale::Royale>),
+                    practice::clear_on_top_out.run_if(is_state_game_over),
+                )
+                    .chain(),
+            )
+                .chain(),
+        )
         .add_systems(
             Update,
             (
                 make_visible,
-                tetris::hold.run_if(is_state_play),
-                tetris::rotate.run_if(is_state_play),
-                tetris::slide.run_if(is_state_play),
-                tetris::fall.run_if(is_state_play),
-                tetris::place.run_if(is_state_place),
-                tetris::clear_block.run_if(is_state_place),
-                tetris::advance.run_if(is_state_advance),
-                tetris::check_advanced_block.run_if(is_state_advance),
-                tetris::update_ghost,
-                field::update_score,
+                net::start.run_if(resource_exists::<net::Connecting>),
+                net::exchange,
+                fumen::export,
+                (editor::paint, editor::edit).chain().run_if(is_state_edit),
+                (
+                    practice::undo
+                        .run_if(is_state_play)
+                        .run_if(resource_exists::<practice::History>),
+                    practice::restore.run_if(resource_exists::<practice::Restore>),
+                    bot::play,
+                    tbp::play.run_if(resource_exists::<tbp::ExternalBot>),
+                )
+                    .chain(),
+                royale::choose_strategy.run_if(resource_exists::<royale::Royale>),
+                net::simulate,
+                (
+                    pc::toggle,
+                    pc::solve.run_if(is_state_play),
+                    pc::step.run_if(resource_exists::<practice::History>),
+                    pc::update_overlay,
+                )
+                    .chain(),
+                (
+                    hint::toggle,
+                    hint::update.run_if(is_state_play),
+                    hint::update_overlay,
+                )
+                    .chain()
+                    .run_if(resource_exists::<hint::PlacementHint>),
+                (
+                    finesse::pick_target.run_if(is_state_play),
+                    finesse::update_overlay,
+                )
+                    .chain()
+                    .run_if(resource_exists::<finesse::Trainer>),
+                (
+                    tetris::update_ghost,
+                    field::update_score,
+                    versus::update_meter,
+                    royale::mark_target.run_if(resource_exists::<royale::Royale>),
+                    mode::update_text,
+                    editor::update_text.run_if(is_state_edit),
+                    mode::on_game_end.run_if(not(is_state_running)),
+                )
+                    .chain(),
             )
                 .chain(),
         )
@@ -0,0 +1,105 @@
+use std::time::Duration;
+
+use crate::*;
+
+pub const MAX_LEVEL: u32 = 999;
+pub const SECTION_LEVELS: u32 = 100;
+const FRAME_TIME: f32 = 1.0 / 60.0;
+
+// (level, gravity in 1/256 rows per frame), 5120 is 20G
+#[rustfmt::skip]
+const GRAVITY: [(u32, u32); 30] = [
+    (0, 4), (30, 6), (35, 8), (40, 10), (50, 12), (60, 16), (70, 32), (80, 48), (90, 64),
+    (100, 80), (120, 96), (140, 112), (160, 128), (170, 144), (200, 4), (220, 32), (230, 64),
+    (233, 96), (236, 128), (239, 160), (243, 192), (247, 224), (251, 256), (300, 512),
+    (330, 768), (360, 1024), (400, 1280), (420, 1024), (450, 768), (500, 5120),
+];
+
+// (level, spawn delay, lock delay, line clear delay) in frames
+#[rustfmt::skip]
+const DELAYS: [(u32, u32, u32, u32); 6] = [
+    (0, 25, 30, 40),
+    (500, 25, 30, 25),
+    (600, 25, 30, 16),
+    (700, 16, 30, 12),
+    (800, 12, 30, 6),
+    (900, 12, 17, 6),
+];
+
+// (score needed, grade)
+#[rustfmt::skip]
+const GRADES: [(u64, &str); 18] = [
+    (0, "9"), (400, "8"), (800, "7"), (1400, "6"), (2000, "5"), (3500, "4"), (5500, "3"),
+    (8000, "2"), (12000, "1"), (16000, "S1"), (22000, "S2"), (30000, "S3"), (40000, "S4"),
+    (52000, "S5"), (66000, "S6"), (82000, "S7"), (100000, "S8"), (120000, "S9"),
+];
+const GM_SCORE: u64 = 126000;
+const GM_TIME: Duration = Duration::from_secs(13 * 60 + 30);
+
+fn frames(frames: u32) -> Duration {
+    Duration::from_secs_f32(frames as f32 * FRAME_TIME)
+}
+
+// the last entry whose level has been reached
+fn find<T: Copy>(table: &[(u32, T)], level: u32) -> T {
+    table
+        .iter()
+        .take_while(|(start, _)| *start <= level)
+        .last()
+        .map(|(_, value)| *value)
+        .unwrap()
+}
+
+pub fn apply_speed(manager: &mut TetrisManager, level: u32) {
+    let gravity = find(&GRAVITY, level);
+    if gravity < 256 {
+        manager.fall_rows = 1;
+        manager
+            .fall_timer
+            .set_duration(Duration::from_secs_f32(256.0 / gravity as f32 * FRAME_TIME));
+    } else {
+        manager.fall_rows = gravity / 256;
+        manager.fall_timer.set_duration(frames(1));
+    }
+
+    let (spawn_delay, lock_delay, line_clear_delay) = find(
+        &DELAYS.map(|(start, spawn, lock, clear)| (start, (spawn, lock, clear))),
+        level,
+    );
+    manager.spawn_delay = frames(spawn_delay);
+    manager.lock_timer.set_duration(frames(lock_delay));
+    manager.line_clear_delay = frames(line_clear_delay);
+}
+
+// every new tetris raises the level by one unless it is stuck at the end of a section,
+// clearing lines raises it by the amount of lines and is the only way past a section
+pub fn advance_level(level: u32, lines: u32, pieces: u32) -> u32 {
+    let mut level = (level + lines).min(MAX_LEVEL);
+    for _ in 0..pieces {
+        if level % SECTION_LEVELS == SECTION_LEVELS - 1 || level >= MAX_LEVEL - 1 {
+            break;
+        }
+        level += 1;
+    }
+    level
+}
+
+pub fn section_goal(level: u32) -> u32 {
+    ((level / SECTION_LEVELS + 1) * SECTION_LEVELS).min(MAX_LEVEL)
+}
+
+pub fn line_score(level: u32, lines: u32) -> u64 {
+    (level + lines).div_ceil(4) as u64 * lines as u64
+}
+
+pub fn grade(score: u64, level: u32, time: Duration) -> &'static str {
+    if level >= MAX_LEVEL && score >= GM_SCORE && time <= GM_TIME {
+        return "GM";
+    }
+    GRADES
+        .iter()
+        .take_while(|(needed, _)| *needed <= score)
+        .last()
+        .map(|(_, grade)| *grade)
+        .unwrap()
+}
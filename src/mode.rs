@@ -0,0 +1,926 @@
+use bevy::math::*;
+use bevy::prelude::*;
+use bevy::sprite::*;
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use std::fs;
+use std::time::Duration;
+
+use crate::attack::AttackPreset;
+use crate::field::*;
+use crate::rules::*;
+use crate::tetris;
+use crate::*;
+
+pub const SPRINT_LINES: [u32; 3] = [20, 40, 100];
+pub const SPLIT_LINES: u32 = 10;
+pub const ULTRA_SECS: u64 = 120;
+pub const LEADERBOARD_SIZE: usize = 10;
+pub const MARATHON_LINES: u32 = 150;
+pub const LEVEL_LINES: u32 = 10;
+pub const MIN_FALL_TIME: f32 = 1.0 / 60.0;
+pub const DIG_ROWS: u32 = 10;
+// rows at the top of the field that start without garbage
+pub const DIG_FREE_ROWS: u32 = 4;
+pub const MAX_DIG_ROWS: u32 = field::GRID_HEIGHT - DIG_FREE_ROWS;
+
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameMode {
+    Endless,
+    Sprint {
+        lines: u32,
+    },
+    Ultra {
+        secs: u64,
+    },
+    Marathon {
+        lines: u32,
+        endless: bool,
+    },
+    // messiness is the chance in percent that the hole moves between rows,
+    // a rise interval of 0 means no garbage is added after the start
+    Dig {
+        rows: u32,
+        messiness: u32,
+        rise_secs: u64,
+    },
+    Master,
+    Classic {
+        level: u32,
+    },
+    Zen {
+        undo: u32,
+    },
+    Editor,
+    // random target placements that have to be reached with the fewest inputs
+    Finesse,
+    // two players on one keyboard sending garbage to each other
+    Versus {
+        attack: AttackPreset,
+    },
+    // versus against another instance over the network, the host picks the attack table
+    Online,
+    // the player against up to 98 bots, everyone attacks the board they target
+    Royale {
+        bots: u32,
+        attack: AttackPreset,
+    },
+    // two players on one keyboard sharing a wider field, each with their own tetris
+    Coop {
+        width: u32,
+    },
+}
+// games on other field sizes, with other pieces or in big mode keep their records apart from
+// the standard ones
+fn record_path(mut path: String, size: UVec2, rules: &Rules) -> String {
+    if size != STANDARD_SIZE {
+        write!(path, "_{}x{}", size.x, size.y).unwrap();
+    }
+    if !rules.pieces.is_standard() {
+        write!(path, "_{}", rules.pieces.name).unwrap();
+    }
+    if rules.big {
+        path.push_str("_big");
+    }
+    path
+}
+
+impl GameMode {
+    // usage: tetry [--field <columns>x<rows>] [--pieces <piece file>] [--big] [--next <0 to 6>]
+    //              [endless | sprint [20 | 40 | 100] | ultra [seconds] | marathon [lines] [endless]
+    //              | dig [rows] [messiness] [rise seconds] | master
+    //              | classic [start level] | zen [undo limit] | edit [setup name] | finesse
+    //              | versus [guideline | tetrio | ppt]
+    //              | online host [port] [guideline | tetrio | ppt] | online join <address:port>
+    //              | royale [bots] [guideline | tetrio | ppt] | coop [width]]
+    pub fn from_args(mut args: impl Iterator<Item = String>) -> Self {
+        match args.next().as_deref() {
+            Some("sprint") => {
+                let lines = args
+                    .next()
+                    .and_then(|s| s.parse::<u32>().ok())
+                    .filter(|lines| SPRINT_LINES.contains(lines))
+                    .unwrap_or(40);
+                Self::Sprint { lines }
+            }
+            Some("ultra") => {
+                let secs = args
+                    .next()
+                    .and_then(|s| s.parse::<u64>().ok())
+                    .filter(|&secs| secs > 0)
+                    .unwrap_or(ULTRA_SECS);
+                Self::Ultra { secs }
+            }
+            Some("marathon") => {
+                let mut lines = MARATHON_LINES;
+                let mut endless = false;
+                for arg in args {
+                    if arg == "endless" {
+                        endless = true;
+                    } else if let Some(arg_lines) = arg.parse::<u32>().ok().filter(|&l| l > 0) {
+                        lines = arg_lines;
+                    }
+                }
+                Self::Marathon { lines, endless }
+            }
+            Some("dig") => {
+                let mut numbers = args.filter_map(|s| s.parse::<u64>().ok());
+                let rows = numbers
+                    .next()
+                    .map_or(DIG_ROWS, |rows| rows.clamp(1, MAX_DIG_ROWS as u64) as u32);
+                let messiness = numbers
+                    .next()
+                    .map_or(100, |messiness| messiness.min(100) as u32);
+                let rise_secs = numbers.next().unwrap_or(0);
+                Self::Dig {
+                    rows,
+                    messiness,
+                    rise_secs,
+                }
+            }
+            Some("master") => Self::Master,
+            Some("classic") => {
+                let level = args
+                    .next()
+                    .and_then(|s| s.parse::<u32>().ok())
+                    .map_or(0, |level| level.min(classic::MAX_START_LEVEL));
+                Self::Classic { level }
+            }
+            Some("zen") => {
+                let undo = args
+                    .next()
+                    .and_then(|s| s.parse::<u32>().ok())
+                    .unwrap_or(practice::UNDO_LIMIT as u32);
+                Self::Zen { undo }
+            }
+            Some("edit") => Self::Editor,
+            Some("finesse") => Self::Finesse,
+            Some("versus") => {
+                let attack = args
+                    .next()
+                    .and_then(|s| AttackPreset::from_name(&s))
+                    .unwrap_or(AttackPreset::Guideline);
+                Self::Versus { attack }
+            }
+            Some(net::ONLINE_ARG) => Self::Online,
+            Some("royale") => {
+                let mut bots = royale::MAX_BOTS;
+                let mut attack = AttackPreset::Guideline;
+                for arg in args {
+                    if let Ok(arg_bots) = arg.parse::<u32>() {
+                        bots = arg_bots.clamp(1, royale::MAX_BOTS);
+                    } else if let Some(preset) = AttackPreset::from_name(&arg) {
+                        attack = preset;
+                    }
+                }
+                Self::Royale { bots, attack }
+            }
+            Some("coop") => {
+                let width = args
+                    .next()
+                    .and_then(|s| s.parse::<u32>().ok())
+                    .map_or(coop::DEFAULT_WIDTH, |width| {
+                        width.clamp(coop::MIN_WIDTH, coop::MAX_WIDTH)
+                    });
+                Self::Coop { width }
+            }
+            _ => Self::Endless,
+        }
+    }
+    fn best_path(&self, size: UVec2, rules: &Rules) -> Option<String> {
+        let path = match self {
+            Self::Sprint { lines } => Some(format!("./sprint_{}", lines)),
+            Self::Dig {
+                rows,
+                messiness,
+                rise_secs,
+            } => Some(format!("./dig_{}_{}_{}", rows, messiness, rise_secs)),
+            _ => None,
+        };
+        path.map(|path| record_path(path, size, rules))
+    }
+    // every score based mode keeps its own leaderboard, highest score first
+    fn score_path(&self, size: UVec2, rules: &Rules) -> Option<String> {
+        let path = match self {
+            Self::Endless => Some("./score".to_string()),
+            Self::Ultra { secs } => Some(format!("./ultra_{}", secs)),
+            Self::Marathon {
+                lines,
+                endless: false,
+            } => Some(format!("./marathon_{}", lines)),
+            Self::Marathon { endless: true, .. } => Some("./marathon_endless".to_string()),
+            Self::Master => Some("./master".to_string()),
+            Self::Classic { .. } => Some("./classic".to_string()),
+            _ => None,
+        };
+        path.map(|path| record_path(path, size, rules))
+    }
+    pub fn players(&self) -> usize {
+        match self {
+            Self::Versus { .. } | Self::Online | Self::Coop { .. } => 2,
+            Self::Royale { bots, .. } => *bots as usize + 1,
+            _ => 1,
+        }
+    }
+    // columns and rows of the field, the editor setups, the online protocol and the small fields of
+    // battle royale only know the standard one and co-op only takes the rows of the asked for size
+    pub fn field_size(&self, requested: Option<UVec2>) -> UVec2 {
+        let requested = requested.unwrap_or(STANDARD_SIZE);
+        match self {
+            Self::Editor | Self::Online | Self::Royale { .. } => STANDARD_SIZE,
+            Self::Coop { width } => uvec2(*width, requested.y),
+            _ => requested,
+        }
+    }
+    pub fn time_limit(&self) -> Option<Duration> {
+        match self {
+            Self::Ultra { secs } => Some(Duration::from_secs(*secs)),
+            _ => None,
+        }
+    }
+    pub fn level(&self, lines: u32) -> u32 {
+        match self {
+            Self::Marathon { lines: goal, .. } => {
+                (lines / LEVEL_LINES + 1).min((goal / LEVEL_LINES).max(1))
+            }
+            _ => 1,
+        }
+    }
+    pub fn rules(&self) -> Rules {
+        match self {
+            Self::Classic { .. } => Rules::nes(),
+            Self::Zen { .. } | Self::Editor => Rules {
+                top_out: false,
+                ..Rules::guideline()
+            },
+            Self::Finesse => Rules {
+                hold: false,
+                top_out: false,
+                ..Rules::guideline()
+            },
+            _ => Rules::guideline(),
+        }
+    }
+    pub fn line_score(&self, lines: u32, level: u32) -> u64 {
+        match self {
+            Self::Master => master::line_score(level, lines),
+            Self::Classic { .. } => classic::line_score(level, lines),
+            _ => (lines * lines) as u64 * level as u64,
+        }
+    }
+}
+
+// guideline gravity, how long a tetris takes to fall a row at the given level
+pub fn fall_time(level: u32) -> f32 {
+    let level = level.saturating_sub(1) as f32;
+    (0.8 - level * 0.007).powf(level).max(MIN_FALL_TIME)
+}
+
+// kept on every board next to its score
+#[derive(Component, Clone)]
+pub struct Stats {
+    pub lines: u32,
+    pub level: u32,
+    pub pieces: u32,
+    pub time: Duration,
+    pub splits: Vec<Duration>,
+    // singles, doubles, triples and tetrises
+    pub clears: [u32; 4],
+    // placements made while the placement hint was shown and how many of them followed it
+    pub hinted: u32,
+    pub hint_matches: u32,
+    // placements that took more inputs than needed
+    pub finesse_faults: u32,
+}
+impl Default for Stats {
+    fn default() -> Self {
+        Self {
+            lines: 0,
+            level: 1,
+            pieces: 0,
+            time: Duration::ZERO,
+            splits: Vec::new(),
+            clears: [0; 4],
+            hinted: 0,
+            hint_matches: 0,
+            finesse_faults: 0,
+        }
+    }
+}
+impl Stats {
+    pub fn pps(&self) -> f32 {
+        let secs = self.time.as_secs_f32();
+        if secs > 0.0 {
+            self.pieces as f32 / secs
+        } else {
+            0.0
+        }
+    }
+}
+
+// saved as the final time followed by the splits, all in milliseconds
+#[derive(Resource, Default)]
+pub struct PersonalBest {
+    pub time: Option<Duration>,
+    pub splits: Vec<Duration>,
+    path: Option<String>,
+}
+
+#[derive(Resource, Default)]
+pub struct Leaderboard {
+    pub scores: Vec<u64>,
+    pub rank: Option<usize>,
+    path: Option<String>,
+}
+impl Leaderboard {
+    fn load(path: String) -> Self {
+        let mut scores: Vec<u64> = fs::read_to_string(&path)
+            .map(|s| {
+                s.split_whitespace()
+                    .filter_map(|s| s.parse().ok())
+                    .collect()
+            })
+            .unwrap_or_default();
+        scores.sort_unstable_by(|a, b| b.cmp(a));
+        scores.truncate(LEADERBOARD_SIZE);
+        Self {
+            scores,
+            rank: None,
+            path: Some(path),
+        }
+    }
+    fn submit(&mut self, score: u64) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let rank = self.scores.partition_point(|&s| s >= score);
+        if rank >= LEADERBOARD_SIZE {
+            return;
+        }
+        self.scores.insert(rank, score);
+        self.scores.truncate(LEADERBOARD_SIZE);
+        self.rank = Some(rank);
+
+        let mut contents = String::new();
+        for score in &self.scores {
+            writeln!(contents, "{}", score).unwrap();
+        }
+        if let Err(err) = fs::write(path, contents) {
+            error!("Failed to save leaderboard: {}", err);
+        }
+    }
+}
+
+#[derive(Resource)]
+pub struct GarbageGenerator {
+    pub hole: u32,
+    pub width: u32,
+    pub messiness: u32,
+    pub rise_timer: Option<Timer>,
+}
+impl GarbageGenerator {
+    fn new(width: u32, messiness: u32, rise_secs: u64) -> Self {
+        Self {
+            hole: fastrand::u32(0..width),
+            width,
+            messiness,
+            rise_timer: (rise_secs > 0)
+                .then(|| Timer::new(Duration::from_secs(rise_secs), TimerMode::Repeating)),
+        }
+    }
+    pub fn next_hole(&mut self) -> u32 {
+        if fastrand::u32(0..100) < self.messiness {
+            // never keep the same column, otherwise a messy row could line up with the last one
+            let offset = fastrand::u32(1..self.width);
+            self.hole = (self.hole + offset) % self.width;
+        }
+        self.hole
+    }
+}
+
+#[derive(Component)]
+pub struct ModeText;
+
+#[derive(Component)]
+pub struct ResultsText;
+
+fn write_time(s: &mut String, time: Duration) {
+    let millis = time.as_millis();
+    write!(
+        s,
+        "{}:{:02}.{:03}",
+        millis / 60_000,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+    .unwrap();
+}
+fn write_time_diff(s: &mut String, time: Duration, best: Duration) {
+    if time > best {
+        s.push('+');
+        write_time(s, time - best);
+    } else {
+        s.push('-');
+        write_time(s, best - time);
+    }
+}
+
+pub fn setup(
+    mut commands: Commands,
+    mode: Res<GameMode>,
+    rules: Res<Rules>,
+    size: Res<FieldSize>,
+    font_handle: Res<FontHandle>,
+    mut board_q: Query<(&mut TetrisManager, &mut Stats)>,
+) {
+    for (mut manager, mut stats) in board_q.iter_mut() {
+        match *mode {
+            GameMode::Marathon { .. } => {
+                manager
+                    .fall_timer
+                    .set_duration(Duration::from_secs_f32(fall_time(1)));
+            }
+            GameMode::Master => {
+                stats.level = 0;
+                master::apply_speed(&mut manager, 0);
+            }
+            GameMode::Classic { level } => {
+                stats.level = level;
+                classic::apply_speed(&mut manager, level);
+            }
+            _ => {}
+        }
+    }
+
+    let mut best = PersonalBest {
+        path: mode.best_path(size.0, &rules),
+        ..Default::default()
+    };
+    if let Some(contents) = best
+        .path
+        .as_ref()
+        .and_then(|path| fs::read_to_string(path).ok())
+    {
+        let mut times = contents
+            .split_whitespace()
+            .filter_map(|s| s.parse::<u64>().ok())
+            .map(Duration::from_millis);
+        best.time = times.next();
+        best.splits = times.collect();
+    }
+    commands.insert_resource(best);
+    commands.insert_resource(
+        mode.score_path(size.0, &rules)
+            .map(Leaderboard::load)
+            .unwrap_or_default(),
+    );
+
+    if matches!(
+        *mode,
+        GameMode::Endless | GameMode::Versus { .. } | GameMode::Online | GameMode::Coop { .. }
+    ) {
+        return;
+    }
+
+    let text_style = TextStyle {
+        font: font_handle.0.clone(),
+        font_size: 200.0,
+        color: Color::WHITE,
+    };
+    commands.spawn((
+        Text2dBundle {
+            text: Text {
+                sections: vec![TextSection::new("", text_style.clone())],
+                justify: JustifyText::Right,
+                ..Default::default()
+            },
+            text_anchor: Anchor::TopRight,
+            transform: Transform {
+                translation: (field_top(size.0) - field_size(size.0) * vec2(0.5, 0.0)
+                    + vec2(-4.0, -64.0))
+                .extend(0.0),
+                scale: Vec3::splat(6.0 / text_style.font_size),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        ModeText,
+    ));
+}
+
+pub fn setup_garbage(
+    mut commands: Commands,
+    mode: Res<GameMode>,
+    sprite_handle: Res<SpriteHandle>,
+    field_q: Query<(Entity, &Board, &Children), With<Field>>,
+    mut block_q: Query<&mut Transform, With<tetris::Block>>,
+) {
+    let GameMode::Dig {
+        rows,
+        messiness,
+        rise_secs,
+    } = *mode
+    else {
+        return;
+    };
+
+    let (field, board, field_children) = field_q.single();
+    // lower fields leave the same free rows on top
+    let play_size = board.play_size();
+    let rows = rows.min(play_size.y.saturating_sub(DIG_FREE_ROWS)).max(1);
+    let mut generator = GarbageGenerator::new(play_size.x, messiness, rise_secs);
+    let holes: Vec<u32> = (0..rows).map(|_| generator.next_hole()).collect();
+    tetris::insert_garbage(
+        &mut commands,
+        field,
+        field_children,
+        board,
+        &sprite_handle,
+        &holes,
+        &mut block_q,
+    );
+    commands.insert_resource(generator);
+}
+
+pub fn rise_garbage(
+    mut commands: Commands,
+    time: Res<Time>,
+    generator: Option<ResMut<GarbageGenerator>>,
+    sprite_handle: Res<SpriteHandle>,
+    mut field_q: Query<(Entity, &Board, &Children, &mut GameState), With<Field>>,
+    mut block_q: Query<&mut Transform, (With<tetris::Block>, Without<tetris::ActiveTetris>)>,
+    mut active_tetris_q: Query<&mut Transform, With<tetris::ActiveTetris>>,
+) {
+    let Some(mut generator) = generator else {
+        return;
+    };
+    let Some(rise_timer) = generator.rise_timer.as_mut() else {
+        return;
+    };
+    if !rise_timer.tick(time.delta()).just_finished() {
+        return;
+    }
+
+    let hole = generator.next_hole();
+    let (field, board, field_children, mut game_state) = field_q.single_mut();
+    tetris::insert_garbage(
+        &mut commands,
+        field,
+        field_children,
+        board,
+        &sprite_handle,
+        &[hole],
+        &mut block_q,
+    );
+
+    // the new row takes the bottom row, so the active tetris has to stay above everything it was above
+    let mut active_transform = active_tetris_q.single_mut();
+    active_transform.translation.y += board.cell_size().y;
+
+    if block_q
+        .iter()
+        .any(|transform| transform.translation.y > 0.0)
+    {
+        info!("Game over!");
+        *game_state = GameState::GameOver;
+    }
+}
+
+pub fn tick_timer(time: Res<Time>, mut board_q: Query<(&GameState, &mut Stats)>) {
+    for (game_state, mut stats) in board_q.iter_mut() {
+        if !matches!(game_state, GameState::GameOver | GameState::Finish) {
+            stats.time += time.delta();
+        }
+    }
+}
+
+// single player modes end the game on every board at once
+fn end_game(state_q: &mut Query<&mut GameState>) {
+    for mut game_state in state_q.iter_mut() {
+        *game_state = GameState::Finish;
+    }
+}
+
+pub fn check_time_limit(
+    mode: Res<GameMode>,
+    mut state_q: Query<&mut GameState>,
+    mut stats_q: Query<&mut Stats>,
+) {
+    let Some(limit) = mode.time_limit() else {
+        return;
+    };
+    let mut over = false;
+    for mut stats in stats_q.iter_mut() {
+        if stats.time >= limit {
+            stats.time = limit;
+            over = true;
+        }
+    }
+    if !over {
+        return;
+    }
+
+    info!("Time's up!");
+    end_game(&mut state_q);
+}
+
+pub fn check_goal(
+    mode: Res<GameMode>,
+    mut state_q: Query<&mut GameState>,
+    mut board_q: Query<(Entity, &mut TetrisManager, &mut Stats)>,
+    best: Res<PersonalBest>,
+    garbage_q: Query<&Transform, With<tetris::Garbage>>,
+    mut counted: Local<HashMap<Entity, (u32, u32)>>,
+) {
+    for (field, mut manager, mut stats) in board_q.iter_mut() {
+        match *mode {
+            GameMode::Sprint { lines } => {
+                while (stats.splits.len() as u32 + 1) * SPLIT_LINES <= stats.lines.min(lines) {
+                    let time = stats.time;
+                    stats.splits.push(time);
+                }
+
+                if stats.lines < lines {
+                    continue;
+                }
+
+                info!("Finished!");
+                end_game(&mut state_q);
+
+                save_best(&stats, &best);
+            }
+            GameMode::Marathon { lines, endless } => {
+                let level = mode.level(stats.lines);
+                if level != stats.level {
+                    stats.level = level;
+                    manager
+                        .fall_timer
+                        .set_duration(Duration::from_secs_f32(fall_time(level)));
+                }
+
+                if !endless && stats.lines >= lines {
+                    info!("Finished!");
+                    end_game(&mut state_q);
+                }
+            }
+            GameMode::Dig { .. } => {
+                if garbage_rows(&garbage_q) > 0 {
+                    continue;
+                }
+
+                info!("Finished!");
+                end_game(&mut state_q);
+                save_best(&stats, &best);
+            }
+            GameMode::Master => {
+                let counted = counted.entry(field).or_default();
+                let (counted_lines, counted_pieces) = *counted;
+                *counted = (stats.lines, stats.pieces);
+
+                let level = master::advance_level(
+                    stats.level,
+                    stats.lines - counted_lines,
+                    stats.pieces - counted_pieces,
+                );
+                if level != stats.level {
+                    stats.level = level;
+                    master::apply_speed(&mut manager, level);
+                }
+
+                if level >= master::MAX_LEVEL {
+                    info!("Finished!");
+                    end_game(&mut state_q);
+                }
+            }
+            GameMode::Classic { level: start_level } => {
+                let level = classic::level(start_level, stats.lines);
+                if level != stats.level {
+                    stats.level = level;
+                    classic::apply_speed(&mut manager, level);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn garbage_rows(garbage_q: &Query<&Transform, With<tetris::Garbage>>) -> u32 {
+    let mut rows = HashSet::new();
+    for transform in garbage_q.iter() {
+        rows.insert(transform.translation.y.round() as i32);
+    }
+    rows.len() as u32
+}
+
+fn save_best(stats: &Stats, best: &PersonalBest) {
+    if best.time.is_some_and(|best_time| stats.time >= best_time) {
+        return;
+    }
+
+    let mut contents = stats.time.as_millis().to_string();
+    for split in &stats.splits {
+        write!(contents, " {}", split.as_millis()).unwrap();
+    }
+    if let Err(err) = fs::write(best.path.as_ref().unwrap(), contents) {
+        error!("Failed to save personal best: {}", err);
+    }
+}
+
+// submits the score once the game is over, either by topping out or by finishing the mode
+pub fn on_game_end(
+    mut commands: Commands,
+    mut ended: Local<bool>,
+    mode: Res<GameMode>,
+    size: Res<FieldSize>,
+    font_handle: Res<FontHandle>,
+    mut leaderboard: ResMut<Leaderboard>,
+    board_q: Query<(&Score, &Stats), With<MainBoard>>,
+) {
+    if *ended {
+        return;
+    }
+    *ended = true;
+
+    let (score, stats) = board_q.single();
+    leaderboard.submit(score.current_score);
+
+    if !matches!(*mode, GameMode::Marathon { .. }) {
+        return;
+    }
+
+    let mut value = String::new();
+    write!(
+        value,
+        "Results\n\nScore\n{}\n\nLines\n{}\n\nLevel\n{}\n\nTime\n",
+        score.current_score, stats.lines, stats.level
+    )
+    .unwrap();
+    write_time(&mut value, stats.time);
+    write!(
+        value,
+        "\n\nSingle  {}\nDouble  {}\nTriple  {}\nTetris  {}",
+        stats.clears[0], stats.clears[1], stats.clears[2], stats.clears[3]
+    )
+    .unwrap();
+    if let Some(rank) = leaderboard.rank {
+        write!(value, "\n\nRank #{}", rank + 1).unwrap();
+    }
+
+    let text_style = TextStyle {
+        font: font_handle.0.clone(),
+        font_size: 200.0,
+        color: Color::WHITE,
+    };
+    commands
+        .spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color: Color::rgba(0.0, 0.0, 0.0, 0.75),
+                    custom_size: Some(tetris::grid_size(size.0)),
+                    ..Default::default()
+                },
+                transform: Transform::from_translation(vec3(0.0, 0.0, 5.0)),
+                ..Default::default()
+            },
+            ResultsText,
+        ))
+        .with_children(|builder| {
+            builder.spawn(Text2dBundle {
+                text: Text {
+                    sections: vec![TextSection::new(value, text_style.clone())],
+                    justify: JustifyText::Center,
+                    ..Default::default()
+                },
+                transform: Transform {
+                    translation: vec3(0.0, 0.0, 1.0),
+                    scale: Vec3::splat(6.0 / text_style.font_size),
+                    ..Default::default()
+                },
+                ..Default::default()
+            });
+        });
+}
+
+pub fn update_text(
+    mode: Res<GameMode>,
+    best: Res<PersonalBest>,
+    leaderboard: Res<Leaderboard>,
+    garbage_q: Query<&Transform, With<tetris::Garbage>>,
+    royale: Option<Res<royale::Royale>>,
+    board_q: Query<(&Score, &Stats, Option<&royale::Contender>), With<MainBoard>>,
+    mut text_q: Query<&mut Text, With<ModeText>>,
+) {
+    let Ok(mut text) = text_q.get_single_mut() else {
+        return;
+    };
+    let (score, stats, contender) = board_q.single();
+
+    let value = &mut text.sections[0].value;
+    value.clear();
+
+    match *mode {
+        GameMode::Endless => {}
+        GameMode::Sprint { lines } => {
+            write!(
+                value,
+                "Lines\n{}/{}\n\nTime\n",
+                stats.lines.min(lines),
+                lines
+            )
+            .unwrap();
+            write_time(value, stats.time);
+            write!(value, "\n\nPPS\n{:.2}", stats.pps()).unwrap();
+
+            if let Some(&split) = stats.splits.last() {
+                let index = stats.splits.len() - 1;
+                write!(value, "\n\n{} lines\n", (index as u32 + 1) * SPLIT_LINES).unwrap();
+                write_time(value, split);
+                if let Some(&best_split) = best.splits.get(index) {
+                    value.push('\n');
+                    write_time_diff(value, split, best_split);
+                }
+            }
+
+            if let Some(best_time) = best.time {
+                value.push_str("\n\nBest\n");
+                write_time(value, best_time);
+            }
+        }
+        GameMode::Ultra { .. } => {
+            let limit = mode.time_limit().unwrap();
+            value.push_str("Time left\n");
+            write_time(value, limit.saturating_sub(stats.time));
+            write!(
+                value,
+                "\n\nLines\n{}\n\nPPS\n{:.2}",
+                stats.lines,
+                stats.pps()
+            )
+            .unwrap();
+
+            if let Some(rank) = leaderboard.rank {
+                write!(value, "\n\nRank\n#{}", rank + 1).unwrap();
+            }
+        }
+        GameMode::Marathon { lines, endless } => {
+            if endless {
+                write!(value, "Lines\n{}", stats.lines).unwrap();
+            } else {
+                write!(value, "Lines\n{}/{}", stats.lines.min(lines), lines).unwrap();
+            }
+            write!(value, "\n\nLevel\n{}\n\nTime\n", stats.level).unwrap();
+            write_time(value, stats.time);
+        }
+        GameMode::Dig { .. } => {
+            write!(value, "Garbage\n{}\n\nTime\n", garbage_rows(&garbage_q)).unwrap();
+            write_time(value, stats.time);
+            write!(value, "\n\nPieces\n{}", stats.pieces).unwrap();
+
+            if let Some(best_time) = best.time {
+                value.push_str("\n\nBest\n");
+                write_time(value, best_time);
+            }
+        }
+        GameMode::Master => {
+            write!(
+                value,
+                "Grade\n{}\n\nLevel\n{}/{}\n\nTime\n",
+                master::grade(score.current_score, stats.level, stats.time),
+                stats.level,
+                master::section_goal(stats.level)
+            )
+            .unwrap();
+            write_time(value, stats.time);
+        }
+        GameMode::Classic { .. } => {
+            write!(value, "Lines\n{}\n\nLevel\n{}", stats.lines, stats.level).unwrap();
+        }
+        GameMode::Zen { .. } | GameMode::Editor => {
+            write!(
+                value,
+                "Lines\n{}\n\nPieces\n{}\n\nUndo\nZ",
+                stats.lines, stats.pieces
+            )
+            .unwrap();
+            if stats.hinted > 0 {
+                write!(value, "\n\nHint\n{}/{}", stats.hint_matches, stats.hinted).unwrap();
+            }
+            write!(value, "\n\nFaults\n{}", stats.finesse_faults).unwrap();
+        }
+        GameMode::Finesse => {
+            write!(
+                value,
+                "Pieces\n{}\n\nFaults\n{}",
+                stats.pieces, stats.finesse_faults
+            )
+            .unwrap();
+        }
+        GameMode::Versus { .. } | GameMode::Online | GameMode::Coop { .. } => {}
+        GameMode::Royale { .. } => {
+            if let (Some(royale), Some(contender)) = (royale, contender) {
+                royale::write_standing(value, &royale, contender);
+            }
+        }
+    }
+}
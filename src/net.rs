@@ -0,0 +1,387 @@
+use bevy::app::AppExit;
+use bevy::prelude::*;
+use bevy::time::TimeUpdateStrategy;
+use serde::{Deserialize, Serialize};
+
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::attack::*;
+use crate::controls::*;
+use crate::field::*;
+use crate::mode::*;
+use crate::rollback::*;
+use crate::versus::{spawn_result, VersusText};
+use crate::*;
+
+// usage: tetry online host [port] [attack table] | tetry online join <address:port>
+pub const ONLINE_ARG: &str = "online";
+pub const DEFAULT_PORT: u16 = 7878;
+// every tick, including the ones played again, moves the game on by exactly this much time
+const TICK: Duration = Duration::from_nanos(1_000_000_000 / 60);
+// inputs are sampled this many ticks before they are played, which hides short round trips
+const INPUT_DELAY: u32 = 2;
+// the most ticks played on predicted inputs of the other player before waiting for the real ones
+const MAX_ROLLBACK: u32 = 8;
+// the boards are compared every this many ticks
+const HASH_INTERVAL: u32 = 60;
+// how long joining waits for the host to answer, hosting waits for as long as it takes
+const JOIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum NetMessage {
+    Hello { seed: u64, attack: AttackPreset },
+    Input { tick: u32, inputs: Inputs },
+    Hash { tick: u32, hash: u64 },
+}
+
+// the online arguments given on the command line, everything after the mode name
+#[derive(Resource)]
+pub struct NetCommand(pub Vec<String>);
+
+// the connection being made on its own thread, the boards wait until the other player is there
+#[derive(Resource)]
+pub struct Connecting(Mutex<Receiver<Result<Connection, String>>>);
+
+type Connection = (Net, u64, AttackPreset);
+
+// a game played together with another instance, both simulate both boards from the same inputs
+// and play on with a guess of the other player's inputs until the real ones arrive, so garbage
+// is worked out the same way on both sides and never has to be sent
+#[derive(Resource)]
+pub struct Net {
+    stream: TcpStream,
+    lines: Mutex<Receiver<String>>,
+    // the board played on this instance, the host plays the first one
+    pub player: usize,
+    // the next tick to simulate
+    tick: u32,
+    // the first tick the inputs of the other player are still missing for
+    confirmed: u32,
+    local: BTreeMap<u32, Inputs>,
+    // the inputs of the other player, the real ones before the confirmed tick
+    // and the predicted ones after it
+    remote: BTreeMap<u32, Inputs>,
+    // the latest real inputs of the other player, the guess for every tick after them
+    last_remote: Inputs,
+    // the first tick that was played on a wrong prediction
+    rollback: Option<u32>,
+    // the boards right before every tick that can still be played again
+    snapshots: BTreeMap<u32, Vec<BoardState>>,
+    // hashes of one side waiting for the hash of the same tick from the other side
+    local_hashes: HashMap<u32, u64>,
+    remote_hashes: HashMap<u32, u64>,
+    disconnected: bool,
+}
+impl Net {
+    fn send(&mut self, message: &NetMessage) {
+        let line = serde_json::to_string(message).unwrap();
+        if let Err(err) = writeln!(self.stream, "{}", line) {
+            if !self.disconnected {
+                error!("Failed to reach the other player: {}", err);
+                self.disconnected = true;
+            }
+        }
+    }
+
+    fn receive(&mut self, tick: u32, inputs: Inputs) {
+        self.confirmed = tick + 1;
+        self.last_remote = inputs;
+        if self
+            .remote
+            .get(&tick)
+            .is_some_and(|&predicted| predicted != inputs)
+        {
+            // everything from here on gets played again and the later ticks predicted again
+            self.remote.split_off(&tick);
+            self.rollback.get_or_insert(tick);
+        }
+        self.remote.insert(tick, inputs);
+    }
+
+    // the other player is expected to keep holding what they held last
+    fn inputs(&mut self, tick: u32) -> (Inputs, Inputs) {
+        let local = self.local.get(&tick).copied().unwrap_or_default();
+        let remote = *self.remote.entry(tick).or_insert(self.last_remote);
+        (local, remote)
+    }
+}
+
+fn check_hash(tick: u32, local: u64, remote: u64) {
+    if local != remote {
+        error!("The boards went out of sync at tick {}", tick);
+    }
+}
+
+// blocks until the other player is there, the host decides the seed and the attack table
+fn connect(args: &[String]) -> Result<Connection, String> {
+    let (stream, player) = match args.first().map(String::as_str) {
+        Some("host") => {
+            let port = args
+                .iter()
+                .find_map(|arg| arg.parse().ok())
+                .unwrap_or(DEFAULT_PORT);
+            let listener = TcpListener::bind(("0.0.0.0", port)).map_err(|err| err.to_string())?;
+            info!("Waiting for a player on port {}", port);
+            let (stream, address) = listener.accept().map_err(|err| err.to_string())?;
+            info!("{} joined", address);
+            (stream, 0)
+        }
+        Some("join") => {
+            let address = args.get(1).ok_or("no address to join")?;
+            let socket_address = address
+                .to_socket_addrs()
+                .map_err(|err| err.to_string())?
+                .next()
+                .ok_or("no address to join")?;
+            let stream = TcpStream::connect_timeout(&socket_address, JOIN_TIMEOUT)
+                .map_err(|err| err.to_string())?;
+            info!("Joined {}", address);
+            (stream, 1)
+        }
+        _ => return Err("expected host or join".to_string()),
+    };
+    stream.set_nodelay(true).map_err(|err| err.to_string())?;
+
+    let reader = stream.try_clone().map_err(|err| err.to_string())?;
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        for line in BufReader::new(reader).lines().map_while(Result::ok) {
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut net = Net {
+        stream,
+        lines: Mutex::new(receiver),
+        player,
+        tick: 0,
+        confirmed: INPUT_DELAY,
+        local: BTreeMap::new(),
+        remote: BTreeMap::new(),
+        last_remote: Inputs::default(),
+        rollback: None,
+        snapshots: BTreeMap::new(),
+        local_hashes: HashMap::new(),
+        remote_hashes: HashMap::new(),
+        disconnected: false,
+    };
+    let (seed, attack) = if player == 0 {
+        let seed = fastrand::u64(..);
+        let attack = args
+            .iter()
+            .find_map(|arg| AttackPreset::from_name(arg))
+            .unwrap_or(AttackPreset::Guideline);
+        net.send(&NetMessage::Hello { seed, attack });
+        (seed, attack)
+    } else {
+        let line = net
+            .lines
+            .lock()
+            .unwrap()
+            .recv_timeout(JOIN_TIMEOUT)
+            .map_err(|_| "the host didn't answer")?;
+        match serde_json::from_str(&line) {
+            Ok(NetMessage::Hello { seed, attack }) => (seed, attack),
+            _ => return Err(format!("unexpected message {}", line)),
+        }
+    };
+    Ok((net, seed, attack))
+}
+
+pub fn setup(
+    mut commands: Commands,
+    mode: Res<GameMode>,
+    command: Res<NetCommand>,
+    font_handle: Res<FontHandle>,
+    board_q: Query<(Entity, &Board)>,
+) {
+    if *mode != GameMode::Online {
+        return;
+    }
+    let args = command.0.clone();
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        let _ = sender.send(connect(&args));
+    });
+    commands.insert_resource(Connecting(Mutex::new(receiver)));
+    for (field, board) in board_q.iter() {
+        spawn_result(&mut commands, &font_handle, field, board.size, "Waiting");
+    }
+}
+
+// starts the game once the connection is there, nothing is played before that
+pub fn start(
+    mut commands: Commands,
+    connecting: Res<Connecting>,
+    mut board_q: Query<(Entity, &mut Board, &mut TetrisManager, &mut GameState)>,
+    text_q: Query<Entity, With<VersusText>>,
+    mut exit: EventWriter<AppExit>,
+) {
+    let received = connecting.0.lock().unwrap().try_recv();
+    let connection = match received {
+        Ok(connection) => connection,
+        Err(TryRecvError::Empty) => return,
+        Err(TryRecvError::Disconnected) => Err("the connection was lost".to_string()),
+    };
+    commands.remove_resource::<Connecting>();
+    let (mut net, seed, attack) = match connection {
+        Ok(connection) => connection,
+        Err(err) => {
+            error!("Failed to start the online game: {}", err);
+            exit.send(AppExit);
+            return;
+        }
+    };
+    for entity in text_q.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    // both boards get the same tetrises, and the local board is the one the tools follow
+    for (field, mut board, mut manager, mut game_state) in board_q.iter_mut() {
+        board.input = Some(TickInput::default());
+        manager.reseed(seed);
+        // the tetris shown while waiting came from before the seed, so the first tick deals again
+        *game_state = GameState::Advance;
+        if board.player == net.player {
+            commands.entity(field).insert(MainBoard);
+        } else {
+            commands.entity(field).remove::<MainBoard>();
+        }
+    }
+    for tick in 0..INPUT_DELAY {
+        net.local.insert(tick, Inputs::default());
+        net.remote.insert(tick, Inputs::default());
+    }
+    commands.insert_resource(AttackTable::new(attack));
+    commands.insert_resource(TimeUpdateStrategy::ManualDuration(TICK));
+    commands.insert_resource(net);
+}
+
+// runs first every frame, trades inputs with the other player
+pub fn exchange(net: Option<ResMut<Net>>, input: PlayerInput) {
+    let Some(mut net) = net else {
+        return;
+    };
+
+    let mut lines = Vec::new();
+    let mut left = false;
+    {
+        let receiver = net.lines.lock().unwrap();
+        loop {
+            match receiver.try_recv() {
+                Ok(line) => lines.push(line),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    left = true;
+                    break;
+                }
+            }
+        }
+    }
+    if left && !net.disconnected {
+        error!("The other player left");
+        net.disconnected = true;
+    }
+    for line in lines {
+        match serde_json::from_str::<NetMessage>(&line) {
+            Ok(NetMessage::Input { tick, inputs }) => net.receive(tick, inputs),
+            Ok(NetMessage::Hash { tick, hash }) => match net.local_hashes.remove(&tick) {
+                Some(local) => check_hash(tick, local, hash),
+                None => {
+                    net.remote_hashes.insert(tick, hash);
+                }
+            },
+            _ => warn!("Unexpected message {}", line),
+        }
+    }
+
+    // every simulated tick samples the local player once, for the tick that is the delay ahead
+    let tick = net.tick;
+    if let Entry::Vacant(entry) = net.local.entry(tick + INPUT_DELAY) {
+        let inputs = input.sample(&Controls::KEYBOARD);
+        entry.insert(inputs);
+        net.send(&NetMessage::Input {
+            tick: tick + INPUT_DELAY,
+            inputs,
+        });
+    }
+}
+
+// plays the ticks of this frame, that is a single one unless an online game has to go back
+// to the first wrong prediction or waits for the other player
+pub fn simulate(world: &mut World, mut systems: Local<Option<RollbackSystems>>) {
+    if world.contains_resource::<Connecting>() {
+        return;
+    }
+    if !world.contains_resource::<Net>() {
+        world.run_schedule(GameTick);
+        return;
+    }
+    let systems = *systems.get_or_insert_with(|| RollbackSystems::new(world));
+
+    let mut net = world.resource_mut::<Net>();
+    let end = if net.tick < net.confirmed + MAX_ROLLBACK {
+        net.tick + 1
+    } else {
+        net.tick
+    };
+    let start = match net.rollback.take() {
+        Some(tick) => {
+            let states = net.snapshots[&tick].clone();
+            world
+                .run_system_with_input(systems.restore, states)
+                .unwrap();
+            tick
+        }
+        None => net.tick,
+    };
+
+    for tick in start..end {
+        let states = world.run_system(systems.save).unwrap();
+        let mut net = world.resource_mut::<Net>();
+        net.snapshots.insert(tick, states);
+        let player = net.player;
+        let (local, remote) = net.inputs(tick);
+        for mut board in world.query::<&mut Board>().iter_mut(world) {
+            let current = if board.player == player {
+                local
+            } else {
+                remote
+            };
+            let previous = board.input.unwrap_or_default().current;
+            board.input = Some(TickInput { current, previous });
+        }
+        world.run_schedule(GameTick);
+    }
+
+    // the ticks both players have the inputs for can't change anymore, so they get compared
+    let mut net = world.resource_mut::<Net>();
+    net.tick = end;
+    let settled = net.confirmed.min(net.tick);
+    let snapshots = net.snapshots.split_off(&settled);
+    let settled_snapshots = mem::replace(&mut net.snapshots, snapshots);
+    net.local = net.local.split_off(&settled);
+    net.remote = net.remote.split_off(&settled);
+    for (tick, states) in settled_snapshots {
+        if !tick.is_multiple_of(HASH_INTERVAL) {
+            continue;
+        }
+        let hash = hash_boards(&states);
+        net.send(&NetMessage::Hash { tick, hash });
+        match net.remote_hashes.remove(&tick) {
+            Some(remote) => check_hash(tick, hash, remote),
+            None => {
+                net.local_hashes.insert(tick, hash);
+            }
+        }
+    }
+}
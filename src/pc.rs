@@ -0,0 +1,355 @@
+use bevy::prelude::*;
+
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+use crate::field::*;
+use crate::grid::*;
+use crate::mode::*;
+use crate::rules::*;
+use crate::*;
+
+// perfect clears higher than this aren't searched for
+pub const PC_MAX_HEIGHT: u32 = 6;
+// bounds the time a search can take on boards without a solution
+const SEARCH_LIMIT: usize = 200_000;
+struct Search<'a> {
+    rules: &'a Rules,
+    queue: &'a [usize],
+    failed: HashSet<(Grid, u32, usize, Option<usize>, bool)>,
+    nodes: usize,
+    // set when the board changed and the search isn't needed anymore
+    cancelled: &'a AtomicBool,
+}
+impl Search<'_> {
+    // queue_index is the index of the current tetris in the queue
+    fn solve(
+        &mut self,
+        grid: &Grid,
+        height: u32,
+        queue_index: usize,
+        hold: Option<usize>,
+        hold_used: bool,
+    ) -> Option<Vec<Spot>> {
+        let &current = self.queue.get(queue_index)?;
+        let empty = height * grid.width - grid.filled();
+        let pieces = self.queue.len() - queue_index + hold.is_some() as usize;
+        // every piece fills as many cells as the pieces have, or at most as many as the largest one
+        let unit = cell_unit(self.rules);
+        let most = self.rules.pieces.max_cell_count() as u32;
+        if !empty.is_multiple_of(unit)
+            || empty > pieces as u32 * most
+            || !grid.can_fill(height, unit)
+        {
+            return None;
+        }
+        let key = (grid.clone(), height, queue_index, hold, hold_used);
+        if self.nodes >= SEARCH_LIMIT
+            || self.cancelled.load(Ordering::Relaxed)
+            || self.failed.contains(&key)
+        {
+            return None;
+        }
+        self.nodes += 1;
+
+        // the tetris to place, where the next one comes from and what ends up held
+        let mut options = vec![(current, false, queue_index + 1, hold)];
+        if self.rules.hold && !hold_used {
+            match hold {
+                Some(held) if held != current => {
+                    options.push((held, true, queue_index + 1, Some(current)))
+                }
+                Some(_) => {}
+                None => {
+                    if let Some(&next) = self.queue.get(queue_index + 1) {
+                        options.push((next, true, queue_index + 2, Some(current)));
+                    }
+                }
+            }
+        }
+
+        for (index, from_hold, next_index, hold) in options {
+            for step in grid.placements(index, self.rules) {
+                if step
+                    .cells(self.rules)
+                    .iter()
+                    .any(|&(_, y)| y >= height as i32)
+                {
+                    continue;
+                }
+                let mut next_grid = grid.clone();
+                let cleared = next_grid.place(&step, self.rules);
+                let step = Spot {
+                    hold: from_hold,
+                    ..step
+                };
+                if next_grid.rows.is_empty() {
+                    return Some(vec![step]);
+                }
+                if let Some(mut spots) =
+                    self.solve(&next_grid, height - cleared, next_index, hold, false)
+                {
+                    spots.insert(0, step);
+                    return Some(spots);
+                }
+            }
+        }
+
+        self.failed.insert(key);
+        None
+    }
+}
+
+// the cells every placement fills, 1 when the pieces have different amounts
+fn cell_unit(rules: &Rules) -> u32 {
+    rules.pieces.cell_count().unwrap_or(1) as u32
+}
+
+// the search takes out the rows every placement clears, this moves the later placements back up
+// by the cleared rows below them, so the whole solution can be shown on the field as it is now
+fn unclear(grid: &Grid, spots: Vec<Spot>, rules: &Rules) -> Vec<Spot> {
+    let mut grid = grid.clone();
+    // the row on the field as it is now of every row of the searched grid
+    let mut rows: Vec<i32> = (0..GRID_HEIGHT as i32 * 2).collect();
+    let row = |rows: &[i32], y: i32| rows.get(y.max(0) as usize).map_or(y, |&row| row);
+    spots
+        .into_iter()
+        .map(|spot| {
+            let cells = spot.cells(rules);
+            let bottom = cells.iter().map(|&(_, y)| y).min().unwrap_or(0);
+            let unclear_spot = spot.moved(0, row(&rows, bottom) - bottom);
+            for &(x, y) in &cells {
+                if grid.rows.len() <= y as usize {
+                    grid.rows.resize(y as usize + 1, 0);
+                }
+                grid.rows[y as usize] |= 1 << x;
+            }
+            let full_row = grid.full_row();
+            for y in (0..grid.rows.len()).rev() {
+                if grid.rows[y] == full_row {
+                    grid.rows.remove(y);
+                    rows.remove(y);
+                }
+            }
+            unclear_spot
+        })
+        .collect()
+}
+
+// the shortest perfect clear using the current tetris, the visible queue and the hold
+pub fn find(
+    grid: &Grid,
+    queue: &[usize],
+    hold: Option<usize>,
+    hold_used: bool,
+    rules: &Rules,
+    cancelled: &AtomicBool,
+) -> Option<Vec<Spot>> {
+    let filled = grid.filled();
+    let mut search = Search {
+        rules,
+        queue,
+        failed: HashSet::new(),
+        nodes: 0,
+        cancelled,
+    };
+    (grid.height().max(1)..=PC_MAX_HEIGHT)
+        .filter(|height| (height * grid.width - filled).is_multiple_of(cell_unit(rules)))
+        .find_map(|height| search.solve(grid, height, 0, hold, hold_used))
+        .map(|spots| unclear(grid, spots, rules))
+}
+
+#[derive(Resource, Default)]
+pub struct PcHint {
+    pub enabled: bool,
+    // the placed tetris count, hold and field the solution was found for, garbage can change the
+    // field without a placement
+    pub key: Option<(u32, Option<usize>, Grid)>,
+    pub solution: Option<Vec<Spot>>,
+    // the shown step, only practice modes can step through the solution
+    pub step: usize,
+    // searches can take a while, so they run on their own thread and are picked up when done,
+    // only the latest one keeps running
+    search: Option<Running>,
+}
+
+struct Running {
+    thread: JoinHandle<Option<Vec<Spot>>>,
+    cancelled: Arc<AtomicBool>,
+}
+
+#[derive(Component)]
+pub struct PcHintTetris;
+
+pub fn setup(mut commands: Commands) {
+    commands.insert_resource(PcHint::default());
+}
+
+pub fn toggle(button_input: Res<ButtonInput<KeyCode>>, mut hint: ResMut<PcHint>) {
+    if button_input.just_pressed(KeyCode::KeyP) {
+        hint.enabled = !hint.enabled;
+        hint.key = None;
+    }
+}
+
+pub fn solve(
+    mut hint: ResMut<PcHint>,
+    rules: Res<Rules>,
+    board_q: Query<(&Board, &TetrisManager, &Stats, &Children), With<MainBoard>>,
+    view: BoardView,
+) {
+    if !hint.enabled {
+        return;
+    }
+    let (board, manager, stats, field_children) = board_q.single();
+    let Some((active_tetris, _)) = view.active(field_children) else {
+        return;
+    };
+    let hold = view.hold(field_children).flatten();
+    let grid = view.grid(board, field_children);
+    let key = Some((stats.pieces, hold, grid.clone()));
+    if hint.key != key {
+        if let Some(running) = &hint.search {
+            running.cancelled.store(true, Ordering::Relaxed);
+        }
+        let mut queue = vec![active_tetris.index];
+        queue.extend((0..rules.preview_count).map(|slot| manager.next_tetris(slot)));
+        let hold_used = manager.hold;
+        let rules = *rules;
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let search_cancelled = cancelled.clone();
+        let thread =
+            thread::spawn(move || find(&grid, &queue, hold, hold_used, &rules, &search_cancelled));
+        hint.search = Some(Running { thread, cancelled });
+        hint.key = key;
+        hint.solution = None;
+        hint.step = 0;
+        return;
+    }
+
+    if !hint
+        .search
+        .as_ref()
+        .is_some_and(|running| running.thread.is_finished())
+    {
+        return;
+    }
+    let solution = hint
+        .search
+        .take()
+        .unwrap()
+        .thread
+        .join()
+        .unwrap_or_default();
+    match &solution {
+        Some(spots) => info!("Perfect clear in {} tetrises", spots.len()),
+        None => info!("No perfect clear with the visible tetrises"),
+    }
+    hint.solution = solution;
+}
+
+pub fn step(button_input: Res<ButtonInput<KeyCode>>, mut hint: ResMut<PcHint>) {
+    let len = hint.solution.as_ref().map_or(0, |spots| spots.len());
+    if button_input.just_pressed(KeyCode::Period) && hint.step + 1 < len {
+        hint.step += 1;
+    }
+    if button_input.just_pressed(KeyCode::Comma) && hint.step > 0 {
+        hint.step -= 1;
+    }
+}
+
+pub fn update_overlay(
+    mut commands: Commands,
+    hint: Res<PcHint>,
+    sprite_handle: Res<SpriteHandle>,
+    rules: Res<Rules>,
+    board_q: Query<(Entity, &Board), With<MainBoard>>,
+    hint_tetris_q: Query<Entity, With<PcHintTetris>>,
+) {
+    if !hint.is_changed() {
+        return;
+    }
+    for entity in hint_tetris_q.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    let Some(spots) = hint.solution.as_ref().filter(|_| hint.enabled) else {
+        return;
+    };
+    // the rest of the solution is shown faintly behind the current step
+    let (field, board) = board_q.single();
+    for (step, spot) in spots.iter().enumerate().skip(hint.step) {
+        let alpha = if step == hint.step { 0.5 } else { 0.2 };
+        let tetris = spawn_spot(
+            &mut commands,
+            &sprite_handle,
+            field,
+            board,
+            spot,
+            &rules,
+            Color::rgba(1.0, 1.0, 1.0, alpha),
+        );
+        commands.entity(tetris).insert(PcHintTetris);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the spot of the piece in the orientation with its lowest leftmost cell on the given cell
+    fn spot(name: char, orientation: u32, x: i32, y: i32, rules: &Rules) -> Spot {
+        let index = rules
+            .pieces
+            .pieces
+            .iter()
+            .position(|piece| piece.name == name)
+            .unwrap();
+        let spot = Spot {
+            index,
+            orientation,
+            x: 0,
+            y: 0,
+            hold: false,
+        };
+        let &(cx, cy) = spot
+            .cells(rules)
+            .iter()
+            .min_by_key(|&&(x, y)| (y, x))
+            .unwrap();
+        spot.moved(x - cx, y - cy)
+    }
+
+    #[test]
+    fn later_steps_move_above_cleared_rows() {
+        let rules = Rules::guideline();
+        let grid = Grid {
+            width: 10,
+            spawn_x: 0,
+            spawn_y: 0,
+            rows: vec![0b01_1111_1111, 0b00_0000_1111],
+        };
+        // a vertical I clears the bottom row, so the O searched on the row above it lands on
+        // the second row of the field as it is now
+        let first = spot('I', 1, 9, 0, &rules);
+        let second = spot('O', 0, 4, 0, &rules);
+        let spots = unclear(&grid, vec![first, second], &rules);
+        assert_eq!(spots[0], first);
+        assert_eq!(spots[1], second.moved(0, 1));
+    }
+
+    #[test]
+    fn cancelled_search_stops() {
+        let rules = Rules::guideline();
+        let grid = Grid {
+            width: 10,
+            spawn_x: 0,
+            spawn_y: 0,
+            rows: Vec::new(),
+        };
+        let queue: Vec<usize> = (0..7).collect();
+        assert!(find(&grid, &queue, None, false, &rules, &AtomicBool::new(true)).is_none());
+    }
+}
@@ -0,0 +1,399 @@
+use bevy::math::vec2;
+use bevy::prelude::*;
+
+use std::fs;
+use std::ops::Index;
+use std::path::Path;
+use std::ptr;
+use std::sync::OnceLock;
+
+use crate::mode::*;
+use crate::rules::*;
+use crate::tetris::*;
+
+pub const PIECES_ARG: &str = "--pieces";
+const KICKS: [i32; 4] = [1, -1, 2, -2]; // only x axis tests
+
+// a piece the rules can be played with, the cells are in blocks around the point it rotates around
+#[derive(Debug, Clone)]
+pub struct Piece {
+    pub name: char,
+    pub cells: Vec<Vec2>,
+    // which of the block sprites it is drawn with
+    pub sprite: usize,
+    // in blocks from the middle of the top of a standard field
+    pub spawn: Vec2,
+    // the columns a rotation is moved by when it collides, tried in order
+    pub kicks: Vec<i32>,
+    // a quarter turn doesn't change the cells, like the O
+    pub fixed: bool,
+    // a half turn only moves the cells, like the I, S and Z, which is what the NES rotation flips
+    pub flips: bool,
+}
+impl Piece {
+    pub fn new(name: char, cells: Vec<Vec2>, sprite: usize) -> Self {
+        // the top cells spawn in the top row and the middle of the cells in the middle of the field,
+        // moved left when the cells don't line up with the columns that way
+        let top = cells.iter().map(|cell| cell.y).fold(f32::MIN, f32::max);
+        let left = cells.iter().map(|cell| cell.x).fold(f32::MAX, f32::min);
+        let right = cells.iter().map(|cell| cell.x).fold(f32::MIN, f32::max);
+        let mut x = -(left + right) * 0.5;
+        if (x + cells[0].x).fract() == 0.0 {
+            x -= 0.5;
+        }
+        let fixed = turned(&cells, 1) == turned(&cells, 0);
+        let flips = normalized(turned(&cells, 2)) == normalized(turned(&cells, 0));
+        Self {
+            name,
+            spawn: vec2(x, -top - 0.5),
+            cells,
+            sprite,
+            kicks: KICKS.to_vec(),
+            fixed,
+            flips,
+        }
+    }
+    pub fn rect(&self) -> Rect {
+        let start = BLOCK_RECT_START[self.sprite];
+        Rect::from_corners(start, start + BLOCK_SIZE)
+    }
+    // how many different rotations it has
+    pub fn orientations(&self, rules: &Rules) -> u32 {
+        if self.fixed {
+            1
+        } else if self.flips && rules.rotation == RotationSystem::Nes {
+            2
+        } else {
+            4
+        }
+    }
+}
+
+// the cells turned clockwise, doubled so the halves of the blocks become whole numbers
+fn turned(cells: &[Vec2], turns: u32) -> Vec<(i32, i32)> {
+    let mut turned: Vec<(i32, i32)> = cells
+        .iter()
+        .map(|&cell| {
+            let mut cell = cell * 2.0;
+            for _ in 0..turns {
+                cell = vec2(cell.y, -cell.x);
+            }
+            (cell.x.round() as i32, cell.y.round() as i32)
+        })
+        .collect();
+    turned.sort();
+    turned
+}
+
+// the cells moved to the bottom left corner, so cells that only differ by their position compare equal
+fn normalized(cells: Vec<(i32, i32)>) -> Vec<(i32, i32)> {
+    let left = cells.iter().map(|&(x, _)| x).min().unwrap_or(0);
+    let bottom = cells.iter().map(|&(_, y)| y).min().unwrap_or(0);
+    cells
+        .into_iter()
+        .map(|(x, y)| (x - left, y - bottom))
+        .collect()
+}
+
+// the pieces a game deals, the tetrises unless a piece file is given
+#[derive(Debug)]
+pub struct PieceSet {
+    // the file name without the extension, records of other sets are kept apart with it
+    pub name: String,
+    pub pieces: Vec<Piece>,
+}
+impl PieceSet {
+    pub fn standard() -> &'static Self {
+        static STANDARD: OnceLock<PieceSet> = OnceLock::new();
+        STANDARD.get_or_init(|| Self {
+            name: String::new(),
+            pieces: TETRIS_NAMES
+                .iter()
+                .enumerate()
+                .map(|(index, &name)| {
+                    let cells = BLOCK_POSITIONS[index].map(Vec2::from).to_vec();
+                    Piece::new(name, cells, index)
+                })
+                .collect(),
+        })
+    }
+    pub fn is_standard(&self) -> bool {
+        ptr::eq(self, Self::standard())
+    }
+    pub fn count(&self) -> usize {
+        self.pieces.len()
+    }
+    // the cells of every piece when they all have the same amount
+    pub fn cell_count(&self) -> Option<usize> {
+        let count = self.pieces[0].cells.len();
+        self.pieces
+            .iter()
+            .all(|piece| piece.cells.len() == count)
+            .then_some(count)
+    }
+    pub fn max_cell_count(&self) -> usize {
+        self.pieces
+            .iter()
+            .map(|piece| piece.cells.len())
+            .max()
+            .unwrap()
+    }
+    pub fn load(path: &str) -> Result<Self, String> {
+        let contents = fs::read_to_string(path).map_err(|err| err.to_string())?;
+        let name = Path::new(path)
+            .file_stem()
+            .map_or(String::new(), |stem| stem.to_string_lossy().into_owned());
+        Ok(Self {
+            name,
+            pieces: parse(&contents)?,
+        })
+    }
+}
+impl Index<usize> for PieceSet {
+    type Output = Piece;
+    fn index(&self, index: usize) -> &Piece {
+        &self.pieces[index]
+    }
+}
+
+// the piece file given on the command line
+#[derive(Resource)]
+pub struct PiecesPath(pub Option<String>);
+
+pub fn setup(path: Res<PiecesPath>, mode: Res<GameMode>, mut rules: ResMut<Rules>) {
+    let Some(path) = &path.0 else {
+        return;
+    };
+    // setups and the online protocol only know the tetrises
+    if matches!(*mode, GameMode::Editor | GameMode::Online) {
+        warn!("The editor and online games only have the standard pieces");
+        return;
+    }
+    match PieceSet::load(path) {
+        // the pieces are kept for the whole game, so the rules stay cheap to copy
+        Ok(pieces) => rules.pieces = Box::leak(Box::new(pieces)),
+        Err(err) => error!("Failed to load the pieces {}: {}", path, err),
+    }
+}
+
+// every piece starts with a line naming it, followed by optional lines for the sprite, kicks and
+// spawn position and the drawing of its cells from the top row down, '#' for a block:
+//
+// piece T
+// sprite 2
+// kicks 1 -1 2 -2
+// spawn -0.5 -1.5
+// .#.
+// ###
+//
+// empty lines and lines starting with // are skipped
+fn parse(contents: &str) -> Result<Vec<Piece>, String> {
+    let mut lines = contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with("//"))
+        .peekable();
+    let mut pieces = Vec::new();
+    while let Some(line) = lines.next() {
+        let name = match line.split_whitespace().collect::<Vec<_>>()[..] {
+            ["piece", name] if name.chars().count() == 1 => name.chars().next().unwrap(),
+            _ => {
+                return Err(format!(
+                    "expected a piece with a one letter name, found {}",
+                    line
+                ))
+            }
+        };
+        let mut sprite = pieces.len() % BLOCK_RECT_START.len();
+        let mut kicks = None;
+        let mut spawn = None;
+        let mut drawing = Vec::new();
+        while let Some(line) = lines.next_if(|line| !line.starts_with("piece")) {
+            let mut words = line.split_whitespace();
+            let bad = || format!("piece {} has a bad line {}", name, line);
+            match words.next() {
+                Some("sprite") => {
+                    sprite = words
+                        .next()
+                        .and_then(|word| word.parse().ok())
+                        .filter(|&sprite| sprite < BLOCK_RECT_START.len())
+                        .ok_or_else(bad)?
+                }
+                Some("kicks") => {
+                    kicks = Some(
+                        words
+                            .map(str::parse)
+                            .collect::<Result<_, _>>()
+                            .map_err(|_| bad())?,
+                    )
+                }
+                Some("spawn") => {
+                    let numbers: Vec<f32> = words
+                        .map(str::parse)
+                        .collect::<Result<_, _>>()
+                        .map_err(|_| bad())?;
+                    let [x, y] = numbers[..] else {
+                        return Err(bad());
+                    };
+                    spawn = Some(vec2(x, y));
+                }
+                _ if line.chars().all(|c| c == '#' || c == '.') => drawing.push(line),
+                _ => return Err(bad()),
+            }
+        }
+        let cells = drawn_cells(&drawing);
+        if cells.is_empty() {
+            return Err(format!("piece {} has no cells", name));
+        }
+        let mut piece = Piece::new(name, cells, sprite);
+        piece.kicks = kicks.unwrap_or(piece.kicks);
+        piece.spawn = spawn.unwrap_or(piece.spawn);
+        pieces.push(piece);
+    }
+    if pieces.is_empty() {
+        return Err("there are no pieces".to_string());
+    }
+    Ok(pieces)
+}
+
+// the cells relative to the middle of their bounds, which is a cell when the longer side is odd
+// like the T and a corner otherwise like the I, rounded down when the other side doesn't agree
+fn drawn_cells(drawing: &[&str]) -> Vec<Vec2> {
+    let cells: Vec<Vec2> = drawing
+        .iter()
+        .enumerate()
+        .flat_map(|(row, line)| {
+            line.chars()
+                .enumerate()
+                .filter(|&(_, c)| c == '#')
+                .map(move |(column, _)| vec2(column as f32, -(row as f32)))
+        })
+        .collect();
+    if cells.is_empty() {
+        return cells;
+    }
+    let min = cells.iter().copied().fold(Vec2::MAX, Vec2::min);
+    let max = cells.iter().copied().fold(Vec2::MIN, Vec2::max);
+    let half = if (max - min + 1.0).max_element() as u32 % 2 == 1 {
+        0.0
+    } else {
+        0.5
+    };
+    let center = ((min + max) * 0.5 - half).floor() + half;
+    cells.into_iter().map(|cell| cell - center).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(cells: &[Vec2]) -> Vec<(i32, i32)> {
+        let mut cells: Vec<_> = cells
+            .iter()
+            .map(|cell| ((cell.x * 2.0) as i32, (cell.y * 2.0) as i32))
+            .collect();
+        cells.sort();
+        cells
+    }
+
+    #[test]
+    fn drawn_tetrises_match_the_standard_ones() {
+        let pieces = parse(include_str!("../assets/pieces/tetromino_plus.txt")).unwrap();
+        for (piece, standard) in pieces.iter().zip(&PieceSet::standard().pieces) {
+            assert_eq!(piece.name, standard.name);
+            assert_eq!(piece.sprite, standard.sprite);
+            assert_eq!(
+                sorted(&piece.cells),
+                sorted(&standard.cells),
+                "{}",
+                piece.name
+            );
+            assert_eq!(piece.spawn, standard.spawn, "{}", piece.name);
+            assert_eq!((piece.fixed, piece.flips), (standard.fixed, standard.flips));
+        }
+    }
+
+    #[test]
+    fn turns() {
+        let standard = PieceSet::standard();
+        let find = |name| {
+            standard
+                .pieces
+                .iter()
+                .find(|piece| piece.name == name)
+                .unwrap()
+        };
+        assert!(find('O').fixed);
+        assert!(!find('I').fixed && find('I').flips);
+        assert!(!find('S').fixed && find('S').flips);
+        assert!(!find('T').fixed && !find('T').flips);
+        assert_eq!(find('O').orientations(&Rules::nes()), 1);
+        assert_eq!(find('I').orientations(&Rules::nes()), 2);
+        assert_eq!(find('I').orientations(&Rules::guideline()), 4);
+    }
+
+    #[test]
+    fn asset_sets() {
+        let pentominoes = parse(include_str!("../assets/pieces/pentomino.txt")).unwrap();
+        assert_eq!(pentominoes.len(), 18);
+        assert!(pentominoes.iter().all(|piece| piece.cells.len() == 5));
+        let set = PieceSet {
+            name: String::new(),
+            pieces: parse(include_str!("../assets/pieces/tetromino_plus.txt")).unwrap(),
+        };
+        assert_eq!(set.count(), 10);
+        assert_eq!(set.cell_count(), None);
+        assert_eq!(set.max_cell_count(), 5);
+    }
+
+    #[test]
+    fn options() {
+        let pieces = parse(
+            "// a comment\n\
+             \n\
+             piece L\n\
+             sprite 5\n\
+             kicks 1 0 -3\n\
+             spawn 0.5 -2.5\n\
+             #.\n\
+             #.\n\
+             ##\n\
+             piece .\n\
+             #\n",
+        )
+        .unwrap();
+        assert_eq!(pieces.len(), 2);
+        assert_eq!(pieces[0].sprite, 5);
+        assert_eq!(pieces[0].kicks, vec![1, 0, -3]);
+        assert_eq!(pieces[0].spawn, vec2(0.5, -2.5));
+        assert_eq!(
+            sorted(&pieces[0].cells),
+            sorted(&[
+                vec2(0.0, 1.0),
+                vec2(0.0, 0.0),
+                vec2(0.0, -1.0),
+                vec2(1.0, -1.0)
+            ])
+        );
+        // pieces without a sprite take the next one in order
+        assert_eq!(pieces[1].sprite, 1);
+        assert_eq!(pieces[1].cells, vec![Vec2::ZERO]);
+        assert_eq!(pieces[1].kicks, KICKS.to_vec());
+        assert!(pieces[1].fixed);
+    }
+
+    #[test]
+    fn errors() {
+        assert!(parse("").is_err());
+        assert!(parse("// nothing\n").is_err());
+        assert!(parse("piece TT\n#\n").is_err());
+        assert!(parse("#\n").is_err());
+        assert!(parse("piece T\n").is_err());
+        assert!(parse("piece T\n...\n").is_err());
+        assert!(parse("piece T\nsprite 7\n#\n").is_err());
+        assert!(parse("piece T\nkicks 1 x\n#\n").is_err());
+        assert!(parse("piece T\nspawn 1\n#\n").is_err());
+        assert!(parse("piece T\n#x#\n").is_err());
+        assert!(PieceSet::load("assets/pieces/missing.txt").is_err());
+    }
+}
@@ -0,0 +1,236 @@
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+
+use std::collections::VecDeque;
+
+use crate::field::*;
+use crate::mode::*;
+use crate::rules::*;
+use crate::tetris::*;
+use crate::*;
+
+pub const UNDO_LIMIT: usize = 30;
+
+#[derive(Clone)]
+pub struct PlacedBlock {
+    pub position: Vec2,
+    pub rect: Rect,
+    pub garbage: bool,
+}
+
+// everything needed to go back to the moment right before a tetris was placed
+pub struct Snapshot {
+    pub blocks: Vec<PlacedBlock>,
+    pub queue: VecDeque<usize>,
+    pub active: usize,
+    pub hold: Option<usize>,
+    pub hold_used: bool,
+    pub score: Score,
+    pub stats: Stats,
+}
+
+#[derive(Resource)]
+pub struct History {
+    pub snapshots: VecDeque<Snapshot>,
+    pub limit: usize,
+}
+impl History {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            snapshots: VecDeque::with_capacity(limit),
+            limit,
+        }
+    }
+    fn push(&mut self, snapshot: Snapshot) {
+        if self.limit == 0 {
+            return;
+        }
+        if self.snapshots.len() >= self.limit {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(snapshot);
+    }
+}
+
+pub fn setup(mut commands: Commands, mode: Res<GameMode>) {
+    match *mode {
+        GameMode::Zen { undo } => commands.insert_resource(History::new(undo as usize)),
+        GameMode::Editor => commands.insert_resource(History::new(UNDO_LIMIT)),
+        _ => {}
+    }
+}
+
+// runs right before the active tetris gets placed
+pub fn record(
+    mut history: ResMut<History>,
+    board_q: Query<(&TetrisManager, &Stats, &Score), With<MainBoard>>,
+    active_tetris_q: Query<&ActiveTetris>,
+    hold_tetris_q: Query<(&Visibility, &HoldTetris)>,
+    block_q: Query<(&Transform, &Sprite, Has<Garbage>), With<Block>>,
+) {
+    let blocks = block_q
+        .iter()
+        .map(|(transform, sprite, garbage)| PlacedBlock {
+            position: transform.translation.truncate(),
+            rect: sprite.rect.unwrap(),
+            garbage,
+        })
+        .collect();
+    let hold = hold_tetris_q
+        .get_single()
+        .ok()
+        .filter(|(visibility, _)| matches!(visibility, Visibility::Visible))
+        .map(|(_, hold_tetris)| hold_tetris.index);
+    let (manager, stats, score) = board_q.single();
+
+    history.push(Snapshot {
+        blocks,
+        queue: manager.queue.clone(),
+        active: active_tetris_q.single().index,
+        hold,
+        hold_used: manager.hold,
+        score: *score,
+        stats: stats.clone(),
+    });
+}
+
+// a snapshot that replaces the current board once the input handling of this frame is done
+#[derive(Resource)]
+pub struct Restore(pub Snapshot);
+
+pub fn undo(
+    mut commands: Commands,
+    button_input: Res<ButtonInput<KeyCode>>,
+    mut history: ResMut<History>,
+) {
+    if !button_input.just_pressed(KeyCode::KeyZ) {
+        return;
+    }
+    if let Some(snapshot) = history.snapshots.pop_back() {
+        commands.insert_resource(Restore(snapshot));
+    }
+}
+
+// the tetrises the pieces of a snapshot are put back into
+#[derive(SystemParam)]
+pub struct SnapshotTetrises<'w, 's> {
+    active_tetris_q: Query<
+        'w,
+        's,
+        (
+            Entity,
+            &'static Children,
+            &'static mut Transform,
+            &'static mut ActiveTetris,
+        ),
+    >,
+    ghost_tetris_q: Query<'w, 's, (Entity, &'static Children), With<GhostTetris>>,
+    next_tetris_q: Query<'w, 's, (Entity, &'static Children, &'static NextTetris)>,
+    hold_tetris_q: Query<
+        'w,
+        's,
+        (
+            Entity,
+            &'static Children,
+            &'static mut Visibility,
+            &'static mut HoldTetris,
+        ),
+    >,
+}
+
+pub fn restore(
+    mut commands: Commands,
+    restore: Res<Restore>,
+    sprite_handle: Res<SpriteHandle>,
+    mut board_q: Query<
+        (Entity, &Board, &mut TetrisManager, &mut Stats, &mut Score),
+        With<MainBoard>,
+    >,
+    block_entity_q: Query<Entity, With<Block>>,
+    mut tetrises: SnapshotTetrises,
+    mut blocks: PieceBlocks,
+) {
+    let snapshot = &restore.0;
+    commands.remove_resource::<Restore>();
+
+    for entity in block_entity_q.iter() {
+        commands.entity(entity).despawn();
+    }
+    let (field, board, mut manager, mut stats, mut score) = board_q.single_mut();
+    for block in &snapshot.blocks {
+        let entity = spawn_block(
+            &mut commands,
+            field,
+            &sprite_handle,
+            block.rect,
+            block.position,
+        );
+        if block.garbage {
+            commands.entity(entity).insert(Garbage);
+        }
+    }
+
+    manager.queue = snapshot.queue.clone();
+    manager.hold = snapshot.hold_used;
+    manager.reset_timers();
+
+    // the clock keeps running, undoing doesn't take back time
+    let time = stats.time;
+    *stats = snapshot.stats.clone();
+    stats.time = time;
+    *score = snapshot.score;
+
+    let (tetris, children, mut transform, mut active_tetris) =
+        tetrises.active_tetris_q.single_mut();
+    commands.entity(tetris).insert(Visibility::Inherited);
+    active_tetris.index = snapshot.active;
+    active_tetris.rotation_index = 0;
+    blocks.replace(active_tetris.index, tetris, children);
+    transform.translation = board
+        .spawn_position(blocks.piece(active_tetris.index))
+        .extend(0.0);
+    transform.rotation = Quat::default();
+
+    if let Ok((ghost_tetris, ghost_children)) = tetrises.ghost_tetris_q.get_single() {
+        commands.entity(ghost_tetris).insert(Visibility::Inherited);
+        blocks.replace(active_tetris.index, ghost_tetris, ghost_children);
+    }
+    for (next, next_children, next_tetris) in tetrises.next_tetris_q.iter() {
+        blocks.replace(manager.next_tetris(next_tetris.slot), next, next_children);
+    }
+    if let Ok((hold, hold_children, mut hold_vis, mut hold_tetris)) =
+        tetrises.hold_tetris_q.get_single_mut()
+    {
+        if let Some(index) = snapshot.hold {
+            hold_tetris.index = index;
+            blocks.replace(index, hold, hold_children);
+            *hold_vis = Visibility::Visible;
+        } else {
+            *hold_vis = Visibility::Hidden;
+        }
+    }
+}
+
+// topping out only clears the field when the rules don't allow it
+pub fn clear_on_top_out(
+    mut commands: Commands,
+    rules: Res<Rules>,
+    mut board_q: Query<(&mut TetrisManager, &mut GameState, &Children)>,
+    block_q: Query<Entity, With<Block>>,
+) {
+    if rules.top_out {
+        return;
+    }
+
+    for (mut manager, mut game_state, field_children) in board_q.iter_mut() {
+        if *game_state != GameState::GameOver {
+            continue;
+        }
+        info!("Clearing the field");
+        for entity in block_q.iter_many(field_children) {
+            commands.entity(entity).despawn();
+        }
+        manager.reset_timers();
+        *game_state = GameState::Play;
+    }
+}
@@ -0,0 +1,281 @@
+use bevy::ecs::system::{SystemId, SystemParam};
+use bevy::prelude::*;
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use crate::controls::*;
+use crate::field::*;
+use crate::grid::*;
+use crate::mode::*;
+use crate::practice::PlacedBlock;
+use crate::rules::*;
+use crate::tetris::*;
+use crate::versus::*;
+use crate::*;
+
+// everything a tick can change on a board, an online game keeps one for every tick
+// that may still have to be played again
+#[derive(Clone)]
+pub struct BoardState {
+    field: Entity,
+    player: usize,
+    input: Option<TickInput>,
+    manager: TetrisManager,
+    stats: Stats,
+    score: Score,
+    game_state: GameState,
+    versus: Option<Versus>,
+    active: ActiveTetris,
+    active_transform: Transform,
+    active_visible: bool,
+    hold: Option<usize>,
+    blocks: Vec<PlacedBlock>,
+}
+
+// only what both instances of an online game agree on goes into the hash,
+// so timers that differ by rounding don't count as a desync
+pub fn hash_boards(states: &[BoardState]) -> u64 {
+    let cell = |position: Vec2| (position / BLOCK_SIZE).round().as_ivec2().to_array();
+    let mut hasher = DefaultHasher::new();
+    for state in states {
+        state.manager.queue.hash(&mut hasher);
+        state.score.current_score.hash(&mut hasher);
+        (state.stats.lines, state.stats.pieces).hash(&mut hasher);
+        (state.active.index, state.active.rotation_index).hash(&mut hasher);
+        cell(state.active_transform.translation.truncate()).hash(&mut hasher);
+        state.hold.hash(&mut hasher);
+        let mut cells: Vec<_> = state
+            .blocks
+            .iter()
+            .map(|block| cell(block.position))
+            .collect();
+        cells.sort();
+        cells.hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+// the blocks and tetrises shown on the boards, put back in place from a board state
+#[derive(SystemParam)]
+pub struct BoardPieces<'w, 's> {
+    sprite_handle: Res<'w, SpriteHandle>,
+    rules: Res<'w, Rules>,
+    block_entity_q: Query<'w, 's, Entity, With<Block>>,
+    versus_text_q: Query<'w, 's, Entity, With<VersusText>>,
+    active_tetris_q: Query<
+        'w,
+        's,
+        (
+            &'static Children,
+            &'static mut Transform,
+            &'static mut ActiveTetris,
+        ),
+    >,
+    ghost_tetris_q: Query<'w, 's, (Entity, &'static Children), With<GhostTetris>>,
+    next_field_q: Query<'w, 's, &'static Children, With<NextField>>,
+    next_tetris_q: Query<'w, 's, (Entity, &'static Children, &'static NextTetris)>,
+    hold_field_q: Query<'w, 's, &'static Children, With<HoldField>>,
+    hold_tetris_q: Query<
+        'w,
+        's,
+        (
+            Entity,
+            &'static Children,
+            &'static mut Visibility,
+            &'static mut HoldTetris,
+        ),
+    >,
+    block_q: Query<'w, 's, (&'static mut Transform, &'static mut Sprite), Without<ActiveTetris>>,
+}
+impl BoardPieces<'_, '_> {
+    fn restore(&mut self, commands: &mut Commands, field_children: &Children, state: &BoardState) {
+        // the blocks are spawned again and a result shown too early goes away
+        for entity in self.block_entity_q.iter_many(field_children) {
+            commands.entity(entity).despawn();
+        }
+        for entity in self.versus_text_q.iter_many(field_children) {
+            commands.entity(entity).despawn_recursive();
+        }
+        for block in &state.blocks {
+            let entity = spawn_block(
+                commands,
+                state.field,
+                &self.sprite_handle,
+                block.rect,
+                block.position,
+            );
+            if block.garbage {
+                commands.entity(entity).insert(Garbage);
+            }
+        }
+
+        let visibility = if state.active_visible {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+        if let Some(tetris) = board_child(field_children, &self.active_tetris_q) {
+            let (children, mut transform, mut active_tetris) =
+                self.active_tetris_q.get_mut(tetris).unwrap();
+            *active_tetris = state.active;
+            *transform = state.active_transform;
+            replace(
+                commands,
+                &self.sprite_handle,
+                &self.rules.pieces[active_tetris.index],
+                tetris,
+                children,
+                &mut self.block_q,
+            );
+            commands.entity(tetris).insert(visibility);
+        }
+        if let Some((ghost_tetris, ghost_children)) =
+            self.ghost_tetris_q.iter_many(field_children).next()
+        {
+            replace(
+                commands,
+                &self.sprite_handle,
+                &self.rules.pieces[state.active.index],
+                ghost_tetris,
+                ghost_children,
+                &mut self.block_q,
+            );
+            commands.entity(ghost_tetris).insert(visibility);
+        }
+
+        for next_field_children in self.next_field_q.iter_many(field_children) {
+            for (next, next_children, next_tetris) in
+                self.next_tetris_q.iter_many(next_field_children)
+            {
+                replace(
+                    commands,
+                    &self.sprite_handle,
+                    &self.rules.pieces[state.manager.next_tetris(next_tetris.slot)],
+                    next,
+                    next_children,
+                    &mut self.block_q,
+                );
+            }
+        }
+        for hold_field_children in self.hold_field_q.iter_many(field_children) {
+            let mut hold_tetrises = self.hold_tetris_q.iter_many_mut(hold_field_children);
+            while let Some((hold, hold_children, mut hold_vis, mut hold_tetris)) =
+                hold_tetrises.fetch_next()
+            {
+                if let Some(index) = state.hold {
+                    hold_tetris.index = index;
+                    replace(
+                        commands,
+                        &self.sprite_handle,
+                        &self.rules.pieces[index],
+                        hold,
+                        hold_children,
+                        &mut self.block_q,
+                    );
+                    *hold_vis = Visibility::Visible;
+                } else {
+                    *hold_vis = Visibility::Hidden;
+                }
+            }
+        }
+    }
+}
+
+// the one-shot systems an online game saves and restores the boards with
+#[derive(Clone, Copy)]
+pub struct RollbackSystems {
+    pub save: SystemId<(), Vec<BoardState>>,
+    pub restore: SystemId<Vec<BoardState>>,
+}
+impl RollbackSystems {
+    pub fn new(world: &mut World) -> Self {
+        Self {
+            save: world.register_system(save),
+            restore: world.register_system(restore),
+        }
+    }
+}
+
+pub fn save(
+    board_q: Query<(
+        Entity,
+        &Board,
+        &TetrisManager,
+        &Stats,
+        &Score,
+        &GameState,
+        &Children,
+    )>,
+    versus_q: Query<&Versus>,
+    active_tetris_q: Query<(&ActiveTetris, &Transform, Option<&Visibility>)>,
+    view: BoardView,
+) -> Vec<BoardState> {
+    let mut states: Vec<_> = board_q
+        .iter()
+        .filter_map(
+            |(field, board, manager, stats, score, game_state, field_children)| {
+                let (active, active_transform, active_vis) =
+                    active_tetris_q.iter_many(field_children).next()?;
+                Some(BoardState {
+                    field,
+                    player: board.player,
+                    input: board.input,
+                    manager: manager.clone(),
+                    stats: stats.clone(),
+                    score: *score,
+                    game_state: *game_state,
+                    versus: versus_q.get(field).ok().cloned(),
+                    active: *active,
+                    active_transform: *active_transform,
+                    active_visible: !matches!(active_vis, Some(Visibility::Hidden)),
+                    hold: view.hold(field_children).flatten(),
+                    blocks: view
+                        .blocks(field_children)
+                        .map(|(transform, sprite, garbage)| PlacedBlock {
+                            position: transform.translation.truncate(),
+                            rect: sprite.rect.unwrap(),
+                            garbage,
+                        })
+                        .collect(),
+                })
+            },
+        )
+        .collect();
+    states.sort_by_key(|state| state.player);
+    states
+}
+
+pub fn restore(
+    In(states): In<Vec<BoardState>>,
+    mut commands: Commands,
+    mut board_q: Query<(
+        &mut Board,
+        &mut TetrisManager,
+        &mut Stats,
+        &mut Score,
+        &mut GameState,
+        &Children,
+    )>,
+    mut versus_q: Query<&mut Versus>,
+    mut pieces: BoardPieces,
+) {
+    for state in states {
+        let Ok((mut board, mut manager, mut stats, mut score, mut game_state, field_children)) =
+            board_q.get_mut(state.field)
+        else {
+            continue;
+        };
+        pieces.restore(&mut commands, field_children, &state);
+
+        board.input = state.input;
+        *manager = state.manager;
+        *stats = state.stats;
+        *score = state.score;
+        *game_state = state.game_state;
+        if let (Ok(mut versus), Some(state_versus)) = (versus_q.get_mut(state.field), state.versus)
+        {
+            *versus = state_versus;
+        }
+    }
+}
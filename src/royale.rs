@@ -0,0 +1,440 @@
+use bevy::math::*;
+use bevy::prelude::*;
+
+use std::fmt::Write;
+use std::time::Duration;
+
+use crate::attack::*;
+use crate::bot;
+use crate::controls::*;
+use crate::field::*;
+use crate::grid::*;
+use crate::mode::*;
+use crate::rules::*;
+use crate::tetris::*;
+use crate::versus::*;
+use crate::*;
+
+pub const MAX_BOTS: u32 = 98;
+// the bot boards are small fields in columns left and right of the player, alternating sides
+const MINI_SCALE: f32 = 0.2;
+const MINI_SPACING: Vec2 = vec2(24.0, 38.0);
+const MINI_COLUMNS: usize = 7;
+const MINI_ROWS: usize = 7;
+// leaves room for the score text of the player
+const MINI_OFFSET: f32 = 144.0;
+pub const VIEW_WIDTH: f32 = (MINI_OFFSET + MINI_COLUMNS as f32 * MINI_SPACING.x) * 2.0;
+// every board picks a new target this often, and right away once its target is knocked out
+const RETARGET_TIME: f32 = 2.0;
+// badge points needed for every extra quarter of attack
+const BADGE_LEVELS: [u32; 4] = [2, 6, 14, 30];
+// how long a bot looks at a new tetris before it moves, every bot gets its own speed
+const MIN_THINK_TIME: f32 = 0.2;
+const MAX_THINK_TIME: f32 = 1.2;
+const TARGET_COLOR: Color = Color::rgb(1.0, 0.3, 0.3);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    Random,
+    // everyone targeting this board gets the attack
+    Attackers,
+    // the board closest to topping out
+    KOs,
+    // the board with the most badges
+    Badges,
+}
+const STRATEGIES: [Strategy; 4] = [
+    Strategy::Random,
+    Strategy::Attackers,
+    Strategy::KOs,
+    Strategy::Badges,
+];
+const STRATEGY_KEYS: [KeyCode; 4] = [
+    KeyCode::Digit1,
+    KeyCode::Digit2,
+    KeyCode::Digit3,
+    KeyCode::Digit4,
+];
+impl Strategy {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Random => "Random",
+            Self::Attackers => "Attackers",
+            Self::KOs => "KOs",
+            Self::Badges => "Badges",
+        }
+    }
+}
+
+#[derive(Resource)]
+pub struct Royale {
+    pub players: u32,
+    pub alive: u32,
+    retarget_timer: Timer,
+}
+
+// a board taking part in the battle royale
+#[derive(Component)]
+pub struct Contender {
+    pub strategy: Strategy,
+    pub target: Option<Entity>,
+    pub kos: u32,
+    // a point for every knock out plus the points of the board knocked out
+    pub badges: u32,
+    // 1 for the winner, set once the board is out
+    pub place: Option<u32>,
+    // the knock out goes to the board that sent the last garbage
+    pub last_attacker: Option<Entity>,
+}
+impl Contender {
+    fn new(strategy: Strategy) -> Self {
+        Self {
+            strategy,
+            target: None,
+            kos: 0,
+            badges: 0,
+            place: None,
+            last_attacker: None,
+        }
+    }
+    // quarters of attack the badges add
+    pub fn boost(&self) -> u32 {
+        BADGE_LEVELS
+            .iter()
+            .filter(|&&level| self.badges >= level)
+            .count() as u32
+    }
+}
+
+// plays a board through tick inputs, so any number of bots can play next to the keyboard
+#[derive(Component)]
+pub struct HeadlessBot {
+    path: Vec<Spot>,
+    // the placed tetris count the path was found for
+    placed: Option<u32>,
+    think_timer: Timer,
+}
+
+fn is_alive(game_state: &GameState) -> bool {
+    !matches!(game_state, GameState::GameOver | GameState::Finish)
+}
+
+// the player in the middle at full size and the bots as small fields around it
+pub fn board_transform(player: usize) -> Transform {
+    if player == 0 {
+        return Transform::from_translation(field_top(STANDARD_SIZE).extend(0.0));
+    }
+    let slot = (player - 1) / 2;
+    let side = if player % 2 == 1 { -1.0 } else { 1.0 };
+    let column = (slot % MINI_COLUMNS) as f32;
+    let row = (slot / MINI_COLUMNS) as f32;
+    Transform {
+        translation: vec3(
+            side * (MINI_OFFSET + (column + 0.5) * MINI_SPACING.x),
+            ((MINI_ROWS - 1) as f32 * 0.5 - row) * MINI_SPACING.y
+                + field_top(STANDARD_SIZE).y * MINI_SCALE,
+            0.0,
+        ),
+        scale: Vec3::splat(MINI_SCALE),
+        ..Default::default()
+    }
+}
+
+pub fn setup(
+    mut commands: Commands,
+    mode: Res<GameMode>,
+    mut board_q: Query<(Entity, &mut Board)>,
+) {
+    let GameMode::Royale { bots, .. } = *mode else {
+        return;
+    };
+    commands.insert_resource(Royale {
+        players: bots + 1,
+        alive: bots + 1,
+        retarget_timer: Timer::from_seconds(RETARGET_TIME, TimerMode::Repeating),
+    });
+
+    for (field, mut board) in board_q.iter_mut() {
+        if board.player == 0 {
+            commands
+                .entity(field)
+                .insert(Contender::new(Strategy::Random));
+            continue;
+        }
+        let think_time = MIN_THINK_TIME + fastrand::f32() * (MAX_THINK_TIME - MIN_THINK_TIME);
+        board.input = Some(TickInput::default());
+        commands.entity(field).insert((
+            Contender::new(STRATEGIES[fastrand::usize(..STRATEGIES.len())]),
+            HeadlessBot {
+                path: Vec::new(),
+                placed: None,
+                think_timer: Timer::new(Duration::from_secs_f32(think_time), TimerMode::Once),
+            },
+        ));
+    }
+}
+
+pub fn choose_strategy(
+    button_input: Res<ButtonInput<KeyCode>>,
+    mut contender_q: Query<&mut Contender, With<MainBoard>>,
+) {
+    let Some(strategy) = STRATEGY_KEYS
+        .iter()
+        .position(|&key| button_input.just_pressed(key))
+        .map(|index| STRATEGIES[index])
+    else {
+        return;
+    };
+    for mut contender in contender_q.iter_mut() {
+        contender.strategy = strategy;
+        contender.target = None;
+    }
+}
+
+pub fn retarget(
+    time: Res<Time>,
+    mut royale: ResMut<Royale>,
+    mut board_q: Query<(
+        Entity,
+        &Board,
+        &GameState,
+        &Versus,
+        &Children,
+        &mut Contender,
+    )>,
+    block_q: Query<&Transform, With<Block>>,
+) {
+    let all = royale.retarget_timer.tick(time.delta()).just_finished();
+    let alive: Vec<Entity> = board_q
+        .iter()
+        .filter(|(_, _, game_state, ..)| is_alive(game_state))
+        .map(|(field, ..)| field)
+        .collect();
+    if !all
+        && board_q.iter().all(|(_, _, game_state, .., contender)| {
+            !is_alive(game_state) || contender.target.is_some_and(|t| alive.contains(&t))
+        })
+    {
+        return;
+    }
+
+    // how close every board is to topping out, the height of its stack and the garbage waiting
+    let candidates: Vec<_> = board_q
+        .iter()
+        .filter(|(_, _, game_state, ..)| is_alive(game_state))
+        .map(|(field, board, _, versus, field_children, contender)| {
+            let height = Grid::from_blocks(board, block_q.iter_many(field_children)).height();
+            let incoming: u32 = versus.incoming.iter().map(|incoming| incoming.lines).sum();
+            (field, height + incoming, contender.badges, contender.target)
+        })
+        .collect();
+
+    for (field, _, game_state, _, _, mut contender) in board_q.iter_mut() {
+        if !is_alive(game_state) || !all && contender.target.is_some_and(|t| alive.contains(&t)) {
+            continue;
+        }
+        let others: Vec<_> = candidates
+            .iter()
+            .filter(|(other, ..)| *other != field)
+            .collect();
+        let attackers: Vec<_> = others
+            .iter()
+            .filter(|(.., target)| *target == Some(field))
+            .collect();
+        let target = match contender.strategy {
+            Strategy::Attackers if !attackers.is_empty() => attackers
+                .get(fastrand::usize(..attackers.len()))
+                .map(|c| c.0),
+            Strategy::Random | Strategy::Attackers => {
+                (!others.is_empty()).then(|| others[fastrand::usize(..others.len())].0)
+            }
+            // ties are broken at random, so not everyone piles on the same board
+            Strategy::KOs => others
+                .iter()
+                .max_by_key(|(_, danger, ..)| (*danger, fastrand::u32(..)))
+                .map(|c| c.0),
+            Strategy::Badges => others
+                .iter()
+                .max_by_key(|(_, _, badges, _)| (*badges, fastrand::u32(..)))
+                .map(|c| c.0),
+        };
+        contender.target = target;
+    }
+}
+
+// runs before the input handling, every bot board gets the inputs of this tick
+pub fn play_bots(
+    time: Res<Time>,
+    rules: Res<Rules>,
+    mut board_q: Query<(&mut Board, &Stats, &GameState, &Children, &mut HeadlessBot)>,
+    view: BoardView,
+) {
+    for (mut board, stats, game_state, field_children, mut bot) in board_q.iter_mut() {
+        let previous = board.input.unwrap_or_default().current;
+        let mut current = Inputs::default();
+
+        if let Some((active_tetris, transform)) = view
+            .active(field_children)
+            .filter(|_| *game_state == GameState::Play)
+        {
+            let spot = Spot::from_active(active_tetris, transform, &board, &rules);
+            if bot.placed != Some(stats.pieces) {
+                bot.placed = Some(stats.pieces);
+                bot.path.clear();
+                bot.think_timer.reset();
+            }
+            if bot.think_timer.tick(time.delta()).finished() {
+                // gravity or a failed move can take the tetris off the path
+                if !bot.path.contains(&spot) {
+                    let grid = view.grid(&board, field_children);
+                    bot.path = bot::plan(&grid, spot, None, &rules)
+                        .map(|(path, _)| path)
+                        .unwrap_or_default();
+                }
+                if bot.path.contains(&spot) {
+                    let action = bot::next_action(&bot.path, spot, &rules);
+                    // a tap is let go for a tick so the next one counts
+                    if action == Action::SoftDrop || !previous.contains(action) {
+                        current.insert(action);
+                    }
+                }
+            }
+        }
+
+        board.input = Some(TickInput { current, previous });
+    }
+}
+
+// takes the place of the versus delivery, the garbage only goes to the targeted boards
+// and grows with the badges of the sender
+pub fn deliver(
+    table: Res<AttackTable>,
+    mut attacks: EventReader<Attack>,
+    mut board_q: Query<(Entity, &Stats, &GameState, &mut Versus, &mut Contender)>,
+) {
+    for attack in attacks.read() {
+        let Ok((.., contender)) = board_q.get(attack.from) else {
+            continue;
+        };
+        let lines = attack.lines * (4 + contender.boost()) / 4;
+        let mut receivers: Vec<Entity> = Vec::new();
+        if contender.strategy == Strategy::Attackers {
+            receivers = board_q
+                .iter()
+                .filter(|(_, _, game_state, _, other)| {
+                    is_alive(game_state) && other.target == Some(attack.from)
+                })
+                .map(|(field, ..)| field)
+                .collect();
+        }
+        if receivers.is_empty() {
+            receivers.extend(contender.target);
+        }
+
+        for receiver in receivers {
+            let Ok((_, stats, game_state, mut versus, mut contender)) = board_q.get_mut(receiver)
+            else {
+                continue;
+            };
+            if !is_alive(game_state) {
+                continue;
+            }
+            versus.incoming.push_back(Incoming {
+                lines,
+                ready: stats.time + table.delay,
+            });
+            contender.last_attacker = Some(attack.from);
+        }
+    }
+}
+
+// takes the place of the versus result, a board that tops out is ranked and the game goes on
+// until a single board is left
+pub fn knock_out(
+    mut commands: Commands,
+    font_handle: Res<FontHandle>,
+    mut royale: ResMut<Royale>,
+    mut board_q: Query<(Entity, &Board, &mut GameState, &mut Contender)>,
+) {
+    let mut knocked_out = Vec::new();
+    for (field, board, game_state, mut contender) in board_q.iter_mut() {
+        if *game_state != GameState::GameOver || contender.place.is_some() {
+            continue;
+        }
+        let place = royale.alive;
+        royale.alive -= 1;
+        contender.place = Some(place);
+        info!("Player {} is out in place {}", board.player + 1, place);
+        spawn_result(
+            &mut commands,
+            &font_handle,
+            field,
+            board.size,
+            &format!("#{}", place),
+        );
+        knocked_out.push((contender.last_attacker, contender.badges));
+    }
+    if knocked_out.is_empty() {
+        return;
+    }
+    for (attacker, badges) in knocked_out {
+        if let Some((.., mut contender)) = attacker.and_then(|field| board_q.get_mut(field).ok()) {
+            contender.kos += 1;
+            contender.badges += badges + 1;
+        }
+    }
+
+    if royale.alive > 1 {
+        return;
+    }
+    for (field, board, mut game_state, mut contender) in board_q.iter_mut() {
+        if is_alive(&game_state) {
+            info!("Player {} wins", board.player + 1);
+            *game_state = GameState::Finish;
+            contender.place = Some(1);
+            spawn_result(&mut commands, &font_handle, field, board.size, "#1");
+        }
+    }
+}
+
+// the board the player targets stands out among the small fields
+pub fn mark_target(
+    main_q: Query<&Contender, With<MainBoard>>,
+    field_q: Query<(Entity, &Children), With<HeadlessBot>>,
+    mut tile_q: Query<&mut Sprite, With<FrameTile>>,
+) {
+    let target = main_q
+        .get_single()
+        .ok()
+        .and_then(|contender| contender.target);
+    for (field, field_children) in field_q.iter() {
+        let color = if Some(field) == target {
+            TARGET_COLOR
+        } else {
+            Color::WHITE
+        };
+        let mut tiles = tile_q.iter_many_mut(field_children);
+        while let Some(mut sprite) = tiles.fetch_next() {
+            if sprite.color != color {
+                sprite.color = color;
+            }
+        }
+    }
+}
+
+pub fn write_standing(value: &mut String, royale: &Royale, contender: &Contender) {
+    match contender.place {
+        Some(place) => write!(value, "Place\n#{}", place),
+        None => write!(value, "Alive\n{}/{}", royale.alive, royale.players),
+    }
+    .unwrap();
+    write!(
+        value,
+        "\n\nKOs\n{}\n\nBadges\n{} +{}%\n\nTarget 1-4\n{}",
+        contender.kos,
+        contender.badges,
+        contender.boost() * 25,
+        contender.strategy.name()
+    )
+    .unwrap();
+}
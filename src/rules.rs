@@ -0,0 +1,72 @@
+use bevy::prelude::*;
+
+use crate::pieces::*;
+
+pub const PREVIEW_COUNT: usize = 5;
+pub const BIG_ARG: &str = "--big";
+pub const NEXT_ARG: &str = "--next";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RotationSystem {
+    // every tetris rotates around its center through all 4 rotations
+    Simple,
+    // like the simple system, but pieces that look the same upside down like I, S and Z only flip
+    // between 2 rotations
+    Nes,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Randomizer {
+    // every 7 tetrises contain each tetris once, or every piece of another piece set
+    Bag,
+    // rolls one of 8 slots, one more than there are pieces, rerolls once when it hits the extra
+    // slot or repeats the last tetris
+    NesReroll,
+}
+
+// everything that differs between the rule sets a mode can be played with
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct Rules {
+    pub hold: bool,
+    pub ghost: bool,
+    pub hard_drop: bool,
+    pub kicks: bool,
+    pub rotation: RotationSystem,
+    pub randomizer: Randomizer,
+    pub preview_count: usize,
+    // without topping out the field is cleared instead of ending the game
+    pub top_out: bool,
+    pub pieces: &'static PieceSet,
+    // every cell of a tetris is 2 by 2 blocks, so a standard field plays like one of 5 columns
+    pub big: bool,
+}
+impl Rules {
+    pub fn guideline() -> Self {
+        Self {
+            hold: true,
+            ghost: true,
+            hard_drop: true,
+            kicks: true,
+            rotation: RotationSystem::Simple,
+            randomizer: Randomizer::Bag,
+            preview_count: PREVIEW_COUNT,
+            top_out: true,
+            pieces: PieceSet::standard(),
+            big: false,
+        }
+    }
+    pub fn nes() -> Self {
+        Self {
+            hold: false,
+            ghost: false,
+            hard_drop: false,
+            kicks: false,
+            rotation: RotationSystem::Nes,
+            randomizer: Randomizer::NesReroll,
+            preview_count: 1,
+            top_out: true,
+            pieces: PieceSet::standard(),
+            big: false,
+        }
+    }
+}
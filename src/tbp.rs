@@ -0,0 +1,395 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Mutex;
+
+use crate::bot::*;
+use crate::field::*;
+use crate::fumen::{self, Rotation};
+use crate::grid::*;
+use crate::mode::*;
+use crate::rules::*;
+use crate::tetris::*;
+use crate::*;
+
+// usage: tetry [mode] --bot <command> [bot args]
+pub const BOT_ARG: &str = "--bot";
+// the bot sees the field as this many rows, the ones above the field are always empty
+const BOARD_HEIGHT: usize = 40;
+
+// the Tetris Bot Protocol, see https://github.com/tetris-bot-protocol/tbp-spec
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Orientation {
+    North,
+    East,
+    South,
+    West,
+}
+impl Orientation {
+    fn rotation(&self) -> Rotation {
+        match self {
+            Self::North => Rotation::Spawn,
+            Self::East => Rotation::Right,
+            Self::South => Rotation::Reverse,
+            Self::West => Rotation::Left,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Location {
+    #[serde(rename = "type")]
+    pub kind: char,
+    pub orientation: Orientation,
+    pub x: i32,
+    pub y: i32,
+}
+impl Location {
+    // the tetris index and the cells it covers, None for unknown tetrises
+    fn cells(&self) -> Option<(usize, [(i32, i32); 4])> {
+        let index = tetris_from_name(self.kind)?;
+        let piece = fumen::Piece {
+            kind: fumen::FUMEN_TYPES[index],
+            rotation: self.orientation.rotation(),
+            x: self.x,
+            y: self.y,
+        };
+        Some((index, piece.cells().map(|[x, y]| (x, y))))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Move {
+    pub location: Location,
+    pub spin: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum FrontendMessage {
+    Rules,
+    Start {
+        hold: Option<char>,
+        queue: Vec<char>,
+        combo: u32,
+        back_to_back: bool,
+        board: Vec<[Option<char>; GRID_WIDTH as usize]>,
+    },
+    Stop,
+    Suggest,
+    Play {
+        #[serde(rename = "move")]
+        mv: Move,
+    },
+    NewPiece {
+        piece: char,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum BotMessage {
+    Info {
+        name: String,
+        version: String,
+        author: String,
+    },
+    Ready,
+    Error {
+        reason: String,
+    },
+    Suggestion {
+        moves: Vec<Move>,
+    },
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Phase {
+    // waiting for the info and ready messages
+    Info,
+    Ready,
+    // ready for a new game
+    Stopped,
+    // the bot knows the game, a suggestion is asked for on the next tetris
+    Idle,
+    Suggesting,
+    // placing the suggested move
+    Moving,
+    // the bot failed and isn't asked for anything anymore
+    Dead,
+}
+
+// the bot command given on the command line
+#[derive(Resource)]
+pub struct BotCommand(pub Vec<String>);
+
+// an external bot running as a child process, talking through its stdin and stdout
+#[derive(Resource)]
+pub struct ExternalBot {
+    child: Child,
+    stdin: ChildStdin,
+    lines: Mutex<Receiver<String>>,
+    phase: Phase,
+    controller: Controller,
+    target: Option<(usize, [(i32, i32); 4])>,
+    // the tetrises taken from the queue the bot knows about
+    drawn: u32,
+    // the placed tetris count the suggestion was asked for
+    placed: u32,
+    // the field and hold the bot has after the last move
+    expected: Option<(Grid, Option<usize>)>,
+}
+impl ExternalBot {
+    fn send(&mut self, message: &FrontendMessage) {
+        let line = serde_json::to_string(message).unwrap();
+        if let Err(err) = writeln!(self.stdin, "{}", line).and_then(|_| self.stdin.flush()) {
+            error!("Failed to talk to the bot: {}", err);
+            self.phase = Phase::Dead;
+        }
+    }
+}
+impl Drop for ExternalBot {
+    fn drop(&mut self) {
+        let _ = writeln!(self.stdin, r#"{{"type":"quit"}}"#);
+        let _ = self.child.wait();
+    }
+}
+
+pub fn setup(mut commands: Commands, command: Res<BotCommand>) {
+    let Some((program, args)) = command.0.split_first() else {
+        return;
+    };
+
+    let mut child = match Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(err) => {
+            error!("Failed to start the bot {}: {}", program, err);
+            return;
+        }
+    };
+
+    let stdin = child.stdin.take().unwrap();
+    let stdout = child.stdout.take().unwrap();
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    info!("Started the bot {}", program);
+    commands.insert_resource(ExternalBot {
+        child,
+        stdin,
+        lines: Mutex::new(receiver),
+        phase: Phase::Info,
+        controller: Controller::default(),
+        target: None,
+        drawn: 0,
+        placed: 0,
+        expected: None,
+    });
+}
+
+fn board_rows<'a>(
+    blocks: impl Iterator<Item = (&'a Transform, &'a Sprite, bool)>,
+) -> Vec<[Option<char>; GRID_WIDTH as usize]> {
+    let mut board = vec![[None; GRID_WIDTH as usize]; BOARD_HEIGHT];
+    for (transform, sprite, garbage) in blocks {
+        let Some((column, row)) = cell_at(STANDARD_SIZE, transform.translation.truncate()) else {
+            continue;
+        };
+        let name = sprite
+            .rect
+            .and_then(|rect| BLOCK_RECT_START.iter().position(|&start| start == rect.min))
+            .filter(|_| !garbage)
+            .map_or(GARBAGE_NAME, |index| TETRIS_NAMES[index]);
+        board[row as usize][column as usize] = Some(name);
+    }
+    board
+}
+
+pub fn play(
+    mut keys: ResMut<ButtonInput<KeyCode>>,
+    mut bot: ResMut<ExternalBot>,
+    board_q: Query<(&Board, &TetrisManager, &Stats, &GameState, &Children)>,
+    player_q: Query<(Entity, &Board, Has<MainBoard>)>,
+    rules: Res<Rules>,
+    view: BoardView,
+) {
+    // like the built-in bot, the external one plays the last board the keyboard plays
+    let Some(Ok((board, manager, stats, game_state, field_children))) =
+        played_board(&player_q).map(|field| board_q.get(field))
+    else {
+        return;
+    };
+    if board.size != STANDARD_SIZE || !rules.pieces.is_standard() || rules.big {
+        return; // the protocol only knows standard fields and the tetrises
+    }
+    let controls = &board.controls;
+    let Some((active_tetris, transform)) = view.active(field_children) else {
+        return;
+    };
+    let hold = view.hold(field_children).flatten();
+    // holding into an empty hold takes a tetris from the queue too
+    let drawn = stats.pieces + hold.is_some() as u32;
+    let grid = view.grid(board, field_children);
+
+    let lines: Vec<String> = bot.lines.lock().unwrap().try_iter().collect();
+    for line in lines {
+        match serde_json::from_str::<BotMessage>(&line) {
+            Ok(BotMessage::Info {
+                name,
+                version,
+                author,
+            }) if bot.phase == Phase::Info => {
+                info!("Bot {} {} by {}", name, version, author);
+                bot.send(&FrontendMessage::Rules);
+                bot.phase = Phase::Ready;
+            }
+            Ok(BotMessage::Ready) if bot.phase == Phase::Ready => bot.phase = Phase::Stopped,
+            Ok(BotMessage::Error { reason }) => {
+                error!("The bot failed: {}", reason);
+                bot.phase = Phase::Dead;
+            }
+            Ok(BotMessage::Suggestion { moves }) if bot.phase == Phase::Suggesting => {
+                bot.phase = Phase::Idle;
+                if drawn != bot.drawn || stats.pieces != bot.placed {
+                    // the tetris was placed before the bot answered
+                    bot.expected = None;
+                    continue;
+                }
+                let Some((target, mv)) = moves
+                    .into_iter()
+                    .find_map(|mv| Some((mv.location.cells()?, mv)))
+                else {
+                    error!("The bot has no move");
+                    bot.phase = Phase::Dead;
+                    continue;
+                };
+
+                let mut expected = grid.clone();
+                expected.place_cells(&target.1);
+                let expected_hold = if target.0 == active_tetris.index {
+                    hold
+                } else {
+                    Some(active_tetris.index)
+                };
+                bot.expected = Some((expected, expected_hold));
+                bot.target = Some(target);
+                bot.send(&FrontendMessage::Play { mv });
+                bot.phase = Phase::Moving;
+            }
+            Ok(_) => {}
+            Err(err) => warn!("Unknown bot message {}: {}", line, err),
+        }
+    }
+
+    if matches!(*game_state, GameState::GameOver | GameState::Finish)
+        && matches!(bot.phase, Phase::Idle | Phase::Suggesting | Phase::Moving)
+    {
+        bot.send(&FrontendMessage::Stop);
+        bot.phase = Phase::Stopped;
+    }
+    if *game_state != GameState::Play || bot.phase == Phase::Dead {
+        bot.controller.release(&mut keys);
+        return;
+    }
+    if !bot.controller.release_tap(&mut keys, controls) {
+        return;
+    }
+
+    match bot.phase {
+        Phase::Stopped | Phase::Idle
+            if bot.phase == Phase::Stopped || bot.expected != Some((grid.clone(), hold)) =>
+        {
+            // the field changed without the bot, by garbage, undoing or a new game
+            if bot.phase == Phase::Idle {
+                bot.send(&FrontendMessage::Stop);
+            }
+            let mut queue = vec![TETRIS_NAMES[active_tetris.index]];
+            queue.extend(
+                (0..manager.preview_count).map(|slot| TETRIS_NAMES[manager.next_tetris(slot)]),
+            );
+            bot.send(&FrontendMessage::Start {
+                hold: hold.map(|index| TETRIS_NAMES[index]),
+                queue,
+                combo: 0,
+                back_to_back: false,
+                board: board_rows(view.blocks(field_children)),
+            });
+            bot.drawn = drawn;
+            bot.expected = Some((grid, hold));
+            bot.phase = Phase::Idle;
+        }
+        Phase::Idle => {
+            // the last visible tetrises are the ones that came into view
+            let new = (drawn - bot.drawn) as usize;
+            let visible: Vec<usize> = manager
+                .queue
+                .iter()
+                .take(manager.preview_count + 1)
+                .copied()
+                .collect();
+            for &index in &visible[visible.len().saturating_sub(new)..] {
+                bot.send(&FrontendMessage::NewPiece {
+                    piece: TETRIS_NAMES[index],
+                });
+            }
+            bot.drawn = drawn;
+            bot.placed = stats.pieces;
+            bot.send(&FrontendMessage::Suggest);
+            bot.phase = Phase::Suggesting;
+        }
+        Phase::Moving if stats.pieces != bot.placed => {
+            bot.controller.path.clear();
+            bot.phase = Phase::Idle;
+        }
+        Phase::Moving => {
+            let (index, mut cells) = bot.target.unwrap();
+            if index != active_tetris.index {
+                if manager.hold {
+                    warn!("The bot can't hold twice");
+                    bot.expected = None;
+                    bot.phase = Phase::Idle;
+                } else {
+                    bot.controller.press(&mut keys, controls.hold);
+                }
+                return;
+            }
+
+            let current = Spot::from_active(active_tetris, transform, board, &rules);
+            if !bot.controller.path.contains(&current) {
+                cells.sort();
+                let path = grid
+                    .reach(current, &rules)
+                    .into_iter()
+                    .find_map(|(spot, path)| {
+                        let mut spot_cells = spot.cells(&rules);
+                        spot_cells.sort();
+                        (spot_cells == cells).then_some(path)
+                    });
+                bot.controller.path = path.unwrap_or_else(|| {
+                    // the move needs rotations these rules don't have, so it's dropped where it is
+                    warn!("The bot move can't be reached");
+                    vec![current]
+                });
+            }
+            bot.controller.follow(&mut keys, current, &rules, controls);
+        }
+        _ => {}
+    }
+}
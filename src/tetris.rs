@@ -1,9 +1,13 @@
 use crate::*;
-use bevy::ecs::query::QueryFilter;
-use bevy::input::*;
+use bevy::ecs::query::{QueryData, QueryFilter};
+use bevy::ecs::system::SystemParam;
 use bevy::math::*;
 use bevy::prelude::*;
+use controls::*;
 use field::*;
+use mode::*;
+use pieces::*;
+use rules::*;
 use std::collections::*;
 
 // block order:
@@ -19,6 +23,15 @@ pub mod tetris {
     pub const L: usize = 6;
 }
 
+pub const TETRIS_NAMES: [char; 7] = ['I', 'O', 'T', 'S', 'Z', 'J', 'L'];
+pub const GARBAGE_NAME: char = 'G';
+
+pub fn tetris_from_name(name: char) -> Option<usize> {
+    TETRIS_NAMES
+        .iter()
+        .position(|&n| n == name.to_ascii_uppercase())
+}
+
 pub const BLOCK_RECT_START: [Vec2; 7] = [
     vec2(0.0, 0.0),
     vec2(8.0, 0.0),
@@ -28,12 +41,15 @@ pub const BLOCK_RECT_START: [Vec2; 7] = [
     vec2(8.0 * 5.0, 0.0),
     vec2(8.0 * 6.0, 0.0),
 ];
+pub const GARBAGE_RECT_START: Vec2 = vec2(8.0 * 7.0, 0.0);
 pub const BLOCK_SIZE: Vec2 = vec2(8.0, 8.0);
-pub const GRID_SIZE: Vec2 = vec2(
-    field::GRID_WIDTH as f32 * BLOCK_SIZE.x,
-    field::GRID_HEIGHT as f32 * BLOCK_SIZE.y,
-);
 
+// the size of a field with the given columns and rows
+pub fn grid_size(size: UVec2) -> Vec2 {
+    size.as_vec2() * BLOCK_SIZE
+}
+
+// the standard piece set is made from these, in block order
 #[rustfmt::skip]
 pub const BLOCK_POSITIONS: [[[f32; 2]; 4]; 7] = [
     [[-1.5, 0.5], [-0.5, 0.5], [ 0.5,  0.5], [ 1.5,  0.5]],
@@ -48,15 +64,20 @@ pub const BLOCK_POSITIONS: [[[f32; 2]; 4]; 7] = [
 #[derive(Component)]
 pub struct Block;
 
+#[derive(Component)]
+pub struct Garbage;
+
 #[derive(Component)]
 pub struct HoldTetris {
     pub index: usize,
 }
 
 #[derive(Component)]
-pub struct NextTetris;
+pub struct NextTetris {
+    pub slot: usize,
+}
 
-#[derive(Component)]
+#[derive(Component, Clone, Copy)]
 pub struct ActiveTetris {
     pub index: usize,
     pub rotation_index: usize,
@@ -65,35 +86,84 @@ pub struct ActiveTetris {
 #[derive(Component)]
 pub struct GhostTetris;
 
-fn get_spawn_position(tetris_index: usize) -> Vec2 {
-    (if matches!(tetris_index, tetris::I | tetris::O) {
-        vec2(0.0, -1.0)
-    } else {
-        vec2(-0.5, -1.5)
-    }) * BLOCK_SIZE
+// a block of a tetris at one of the cells of its piece
+fn tetris_block(
+    sprite_handle: &Res<SpriteHandle>,
+    rect: Rect,
+    cell: Vec2,
+    tint: Color,
+) -> SpriteBundle {
+    SpriteBundle {
+        sprite: Sprite {
+            color: tint,
+            rect: Some(rect),
+            ..Default::default()
+        },
+        transform: Transform::from_translation((cell * BLOCK_SIZE).extend(0.0)),
+        texture: sprite_handle.0.clone(),
+        ..Default::default()
+    }
 }
 
-// replaces tetris blocks without deleting and creating new blocks
-fn replace(
-    tetris_index: usize,
+// replaces tetris blocks without deleting and creating new blocks,
+// unless the pieces have different amounts of cells
+pub fn replace(
+    commands: &mut Commands,
+    sprite_handle: &Res<SpriteHandle>,
+    piece: &Piece,
+    tetris: Entity,
     tetris_children: &Children,
     block_q: &mut Query<(&mut Transform, &mut Sprite), impl QueryFilter>,
 ) {
-    let start = BLOCK_RECT_START[tetris_index];
-    let rect = Rect::from_corners(start, start + BLOCK_SIZE);
-    let mut block_position_iter = BLOCK_POSITIONS[tetris_index].iter();
-    for child in tetris_children.iter() {
-        let (mut transform, mut sprite) = block_q.get_mut(*child).unwrap();
-        let pos = Vec2::from(*block_position_iter.next().unwrap()) * BLOCK_SIZE;
+    let rect = piece.rect();
+    let mut tint = Color::WHITE;
+    for (index, &child) in tetris_children.iter().enumerate() {
+        let Some(&cell) = piece.cells.get(index) else {
+            commands.entity(child).despawn_recursive();
+            continue;
+        };
+        let (mut transform, mut sprite) = block_q.get_mut(child).unwrap();
+        let pos = cell * BLOCK_SIZE;
+        tint = sprite.color;
         sprite.rect = Some(rect);
         transform.translation.x = pos.x;
         transform.translation.y = pos.y;
     }
+    for &cell in piece.cells.iter().skip(tetris_children.len()) {
+        commands
+            .spawn(tetris_block(sprite_handle, rect, cell, tint))
+            .set_parent(tetris);
+    }
 }
 
-fn spawn_tetris(
+// replace for the systems that swap the pieces of the active tetris and the previews
+#[derive(SystemParam)]
+pub struct PieceBlocks<'w, 's> {
+    commands: Commands<'w, 's>,
+    sprite_handle: Res<'w, SpriteHandle>,
+    rules: Res<'w, Rules>,
+    block_q: Query<'w, 's, (&'static mut Transform, &'static mut Sprite), Without<ActiveTetris>>,
+}
+impl PieceBlocks<'_, '_> {
+    pub fn piece(&self, index: usize) -> &'static Piece {
+        &self.rules.pieces[index]
+    }
+    pub fn replace(&mut self, index: usize, tetris: Entity, tetris_children: &Children) {
+        let piece = self.piece(index);
+        replace(
+            &mut self.commands,
+            &self.sprite_handle,
+            piece,
+            tetris,
+            tetris_children,
+            &mut self.block_q,
+        );
+    }
+}
+
+pub fn spawn_tetris(
     commands: &mut Commands,
-    tetris_index: usize,
+    piece: &Piece,
     sprite_handle: &Res<SpriteHandle>,
     tint: Color,
 ) -> Entity {
@@ -101,93 +171,200 @@ fn spawn_tetris(
         .spawn((TransformBundle::default(), InheritedVisibility::VISIBLE))
         .id();
 
-    let min = BLOCK_RECT_START[tetris_index];
-    let max = min + BLOCK_SIZE;
-
-    for i in 0..4 {
+    for &cell in &piece.cells {
         commands
-            .spawn(SpriteBundle {
-                sprite: Sprite {
-                    color: tint,
-                    rect: Some(Rect::from_corners(min, max)),
-                    ..Default::default()
-                },
-                transform: Transform::from_translation(
-                    (Vec2::from(BLOCK_POSITIONS[tetris_index][i]) * BLOCK_SIZE).extend(0.0),
-                ),
-                texture: sprite_handle.0.clone(),
-                ..Default::default()
-            })
+            .spawn(tetris_block(sprite_handle, piece.rect(), cell, tint))
             .set_parent(root);
     }
 
     root
 }
 
+// the child of a board the query matches, every board has a single active tetris, ghost tetris,
+// next field and hold field
+pub fn board_child<D: QueryData, F: QueryFilter>(
+    children: &Children,
+    query: &Query<D, F>,
+) -> Option<Entity> {
+    children
+        .iter()
+        .copied()
+        .find(|&child| query.contains(child))
+}
+
+// the children of the field a board places its blocks in, in co-op the second player places
+// them in the field of the first one
+pub fn placed_children<'a>(
+    board: &Board,
+    field_children: &'a Children,
+    children_q: &'a Query<&Children, impl QueryFilter>,
+) -> &'a Children {
+    board
+        .shared
+        .and_then(|field| children_q.get(field).ok())
+        .unwrap_or(field_children)
+}
+
+// the placed blocks of a board, relative to the field space
+pub fn board_blocks(
+    field_children: &Children,
+    block_q: &Query<&Transform, impl QueryFilter>,
+) -> Vec<Vec2> {
+    block_q
+        .iter_many(field_children)
+        .map(|transform| transform.translation.truncate())
+        .collect()
+}
+
+// what the active tetris of a board can't move into besides the walls, the placed blocks of its
+// field and in co-op the active tetris of the other player
+#[derive(SystemParam)]
+pub struct Obstacles<'w, 's> {
+    children_q: Query<'w, 's, &'static Children, With<Board>>,
+    block_q: Query<'w, 's, &'static Transform, (With<Block>, Without<ActiveTetris>)>,
+    transform_q: Query<'w, 's, &'static Transform, Without<ActiveTetris>>,
+}
+impl Obstacles<'_, '_> {
+    pub fn placed(&self, board: &Board, field_children: &Children) -> Vec<Vec2> {
+        board_blocks(
+            placed_children(board, field_children, &self.children_q),
+            &self.block_q,
+        )
+    }
+    pub fn partner<D: QueryData, F: QueryFilter>(
+        &self,
+        board: &Board,
+        tetris_q: &Query<D, F>,
+    ) -> Option<Entity> {
+        let partner_children = self.children_q.get(board.partner?).ok()?;
+        board_child(partner_children, tetris_q)
+    }
+    // the blocks of a tetris, relative to the field space
+    pub fn tetris_blocks(
+        &self,
+        tetris_transform: &Transform,
+        tetris_children: &Children,
+    ) -> Vec<Vec2> {
+        self.transform_q
+            .iter_many(tetris_children)
+            .map(|transform| {
+                tetris_transform
+                    .transform_point(transform.translation)
+                    .truncate()
+            })
+            .collect()
+    }
+    pub fn is_colliding(
+        &self,
+        tetris_transform: &Transform,
+        tetris_children: &Children,
+        size: UVec2,
+        blocks: &[Vec2],
+    ) -> bool {
+        is_tetris_colliding(
+            tetris_transform,
+            tetris_children,
+            size,
+            blocks,
+            &self.transform_q,
+        )
+    }
+}
+
 pub fn setup(
     mut commands: Commands,
     sprite_handle: Res<SpriteHandle>,
-    manager: Res<TetrisManager>,
-    field_q: Query<Entity, With<Field>>,
-    next_field_q: Query<Entity, With<NextField>>,
-    hold_field_q: Query<Entity, With<HoldField>>,
+    rules: Res<Rules>,
+    // the small bot fields of battle royale have no children yet
+    board_q: Query<(Entity, &Board, &TetrisManager, Option<&Children>)>,
+    next_field_q: Query<(), With<NextField>>,
+    hold_field_q: Query<(), With<HoldField>>,
 ) {
-    let field = field_q.single();
-
-    let index = manager.current_tetris();
+    for (field, board, manager, field_children) in board_q.iter() {
+        let index = manager.current_tetris();
+        let piece = &rules.pieces[index];
 
-    let tetris = spawn_tetris(&mut commands, index, &sprite_handle, Color::WHITE);
-    commands
-        .entity(tetris)
-        .insert((
-            ActiveTetris {
-                index,
-                rotation_index: 0,
-            },
-            Transform::from_translation(get_spawn_position(index).extend(0.0)),
-        ))
-        .set_parent(field);
-
-    let ghost_tetris = spawn_tetris(
-        &mut commands,
-        index,
-        &sprite_handle,
-        Color::rgba(1.0, 1.0, 1.0, 0.25),
-    );
-    commands
-        .entity(ghost_tetris)
-        .insert(GhostTetris {})
-        .set_parent(field);
-
-    let next_field = next_field_q.single();
+        let tetris = spawn_tetris(&mut commands, piece, &sprite_handle, Color::WHITE);
+        commands
+            .entity(tetris)
+            .insert((
+                ActiveTetris {
+                    index,
+                    rotation_index: 0,
+                },
+                Transform::from_translation(board.spawn_position(piece).extend(0.0))
+                    .with_scale(board.tetris_scale()),
+            ))
+            .set_parent(field);
 
-    let next_index = manager.next_tetris();
-    let next_tetris = spawn_tetris(&mut commands, next_index, &sprite_handle, Color::WHITE);
-    commands
-        .entity(next_tetris)
-        .insert(NextTetris)
-        .set_parent(next_field);
+        if rules.ghost {
+            let ghost_tetris = spawn_tetris(
+                &mut commands,
+                piece,
+                &sprite_handle,
+                Color::rgba(1.0, 1.0, 1.0, 0.25),
+            );
+            commands
+                .entity(ghost_tetris)
+                .insert(GhostTetris {})
+                .set_parent(field);
+        }
 
-    let hold_field = hold_field_q.single();
+        if let Some(next_field) = field_children.and_then(|c| board_child(c, &next_field_q)) {
+            for slot in 0..manager.preview_count {
+                let next_tetris = spawn_tetris(
+                    &mut commands,
+                    &rules.pieces[manager.next_tetris(slot)],
+                    &sprite_handle,
+                    Color::WHITE,
+                );
+                let transform = if slot == 0 {
+                    Transform::default()
+                } else {
+                    Transform {
+                        translation: vec3(
+                            0.0,
+                            -NEXT_FIELD_RECT.height() * 0.5
+                                - NEXT_QUEUE_SPACING * (slot as f32 - 0.5),
+                            0.0,
+                        ),
+                        scale: Vec3::splat(NEXT_QUEUE_SCALE),
+                        ..Default::default()
+                    }
+                };
+                commands
+                    .entity(next_tetris)
+                    .insert((NextTetris { slot }, transform))
+                    .set_parent(next_field);
+            }
+        }
 
-    let hold_tetris = spawn_tetris(&mut commands, 0, &sprite_handle, Color::WHITE);
-    commands
-        .entity(hold_tetris)
-        .insert((HoldTetris { index: 0 }, Visibility::Hidden))
-        .set_parent(hold_field);
+        if let Some(hold_field) = field_children.and_then(|c| board_child(c, &hold_field_q)) {
+            let hold_tetris = spawn_tetris(
+                &mut commands,
+                &rules.pieces[0],
+                &sprite_handle,
+                Color::WHITE,
+            );
+            commands
+                .entity(hold_tetris)
+                .insert((HoldTetris { index: 0 }, Visibility::Hidden))
+                .set_parent(hold_field);
+        }
+    }
 }
 
-// check if point is colliding with the tetris blocks and walls
+// check if point is colliding with the placed blocks of a board and the walls
 // NOTE: the point needs to be relative to the field space!
-pub fn is_colliding(point: Vec2, block_q: &Query<&Transform, impl QueryFilter>) -> bool {
-    if point.x.abs() > GRID_SIZE.x * 0.5 || point.y < -GRID_SIZE.y {
+pub fn is_colliding(point: Vec2, size: UVec2, blocks: &[Vec2]) -> bool {
+    let grid_size = grid_size(size);
+    if point.x.abs() > grid_size.x * 0.5 || point.y < -grid_size.y {
         return true; // out of grid
     }
 
     let half_size = BLOCK_SIZE * 0.5;
 
-    for transform in block_q.iter() {
-        let block_point = transform.translation;
+    for block_point in blocks {
         let x_diff = (point.x - block_point.x).abs();
         let y_diff = (point.y - block_point.y).abs();
 
@@ -202,7 +379,8 @@ pub fn is_colliding(point: Vec2, block_q: &Query<&Transform, impl QueryFilter>)
 pub fn is_tetris_colliding(
     tetris_transform: &Transform,
     tetris_children: &Children,
-    block_q: &Query<&Transform, impl QueryFilter>,
+    size: UVec2,
+    blocks: &[Vec2],
     transform_q: &Query<&Transform, impl QueryFilter>,
 ) -> bool {
     for &child in tetris_children {
@@ -210,7 +388,7 @@ pub fn is_tetris_colliding(
         let point = tetris_transform
             .transform_point(child_transform.translation)
             .truncate();
-        if is_colliding(point, block_q) {
+        if is_colliding(point, size, blocks) {
             return true;
         }
     }
@@ -220,324 +398,654 @@ pub fn is_tetris_colliding(
 
 pub fn place(
     mut commands: Commands,
-    mut manager: ResMut<TetrisManager>,
-    active_tetris_q: Query<(&Children, &Transform), With<ActiveTetris>>,
+    mut board_q: Query<(
+        Entity,
+        &Board,
+        &mut TetrisManager,
+        &mut Stats,
+        &GameState,
+        &Children,
+    )>,
+    active_tetris_q: Query<(Entity, &Children, &Transform), With<ActiveTetris>>,
+    ghost_tetris_q: Query<Entity, With<GhostTetris>>,
     block_q: Query<(&Sprite, &Handle<Image>, &Transform)>,
-    field_q: Query<Entity, With<Field>>,
 ) {
-    manager.hold = false;
+    for (field, board, mut manager, mut stats, game_state, field_children) in board_q.iter_mut() {
+        if *game_state != GameState::Place {
+            continue;
+        }
+        let Some((tetris, children, tetris_transform)) =
+            active_tetris_q.iter_many(field_children).next()
+        else {
+            continue;
+        };
+        manager.hold = false;
+        stats.pieces += 1;
+
+        // the placed blocks take the place of the tetris until the next one spawns
+        commands.entity(tetris).insert(Visibility::Hidden);
+        if let Some(ghost_tetris) = ghost_tetris_q.iter_many(field_children).next() {
+            commands.entity(ghost_tetris).insert(Visibility::Hidden);
+        }
 
-    let (children, tetris_transform) = active_tetris_q.single();
-    let field = field_q.single();
-    for child in children {
-        let (sprite, texture, child_transform) = block_q.get(*child).unwrap();
-        commands
-            .spawn((
-                SpriteBundle {
-                    sprite: sprite.clone(),
-                    transform: *tetris_transform * *child_transform,
-                    texture: texture.clone(),
-                    ..Default::default()
-                },
-                Block {},
-            ))
-            .set_parent(field);
+        // in big mode every cell is placed as blocks that each show their part of the cell
+        let cell_blocks = board.cell_blocks;
+        let part = BLOCK_SIZE / cell_blocks as f32;
+        for child in children {
+            let (sprite, texture, child_transform) = block_q.get(*child).unwrap();
+            let cell_transform = *tetris_transform * *child_transform;
+            let rect = sprite.rect.unwrap();
+            for (column, row) in
+                (0..cell_blocks).flat_map(|c| (0..cell_blocks).map(move |r| (c, r)))
+            {
+                let offset = vec2(column as f32, row as f32) * part;
+                let position = rect.min + vec2(offset.x, BLOCK_SIZE.y - part.y - offset.y);
+                let center = offset + part * 0.5 - BLOCK_SIZE * 0.5;
+                commands
+                    .spawn((
+                        SpriteBundle {
+                            sprite: Sprite {
+                                rect: Some(Rect::from_corners(position, position + part)),
+                                custom_size: Some(BLOCK_SIZE),
+                                ..sprite.clone()
+                            },
+                            transform: Transform {
+                                translation: cell_transform.transform_point(center.extend(0.0)),
+                                rotation: cell_transform.rotation,
+                                ..Default::default()
+                            },
+                            texture: texture.clone(),
+                            ..Default::default()
+                        },
+                        Block {},
+                    ))
+                    .set_parent(board.shared.unwrap_or(field));
+            }
+        }
     }
 }
 
 pub fn advance(
-    mut manager: ResMut<TetrisManager>,
+    mut board_q: Query<(&Board, &mut TetrisManager, &GameState, &Children)>,
     mut active_tetris_q: Query<(&Children, &mut Transform, &mut ActiveTetris)>,
-    mut block_q: Query<(&mut Transform, &mut Sprite), Without<ActiveTetris>>,
-    ghost_tetris_q: Query<&Children, With<GhostTetris>>,
-    mut next_tetris_q: Query<&Children, With<NextTetris>>,
+    mut blocks: PieceBlocks,
+    ghost_tetris_q: Query<(Entity, &Children), With<GhostTetris>>,
+    next_field_q: Query<&Children, With<NextField>>,
+    next_tetris_q: Query<(Entity, &Children, &NextTetris)>,
 ) {
-    manager.advance();
-
-    let (tetris_children, mut transform, mut active_tetris) = active_tetris_q.single_mut();
-
-    active_tetris.index = manager.current_tetris();
-    replace(active_tetris.index, tetris_children, &mut block_q);
-
-    transform.translation = get_spawn_position(manager.current_tetris()).extend(0.0);
-    transform.rotation = Quat::default();
-
-    let ghost_tetris_children = ghost_tetris_q.single();
-    replace(
-        manager.current_tetris(),
-        ghost_tetris_children,
-        &mut block_q,
-    );
+    for (board, mut manager, game_state, field_children) in board_q.iter_mut() {
+        if *game_state != GameState::Advance {
+            continue;
+        }
+        let Some(active) = board_child(field_children, &active_tetris_q) else {
+            continue;
+        };
+        manager.advance();
+
+        let (tetris_children, mut transform, mut active_tetris) =
+            active_tetris_q.get_mut(active).unwrap();
+
+        active_tetris.index = manager.current_tetris();
+        active_tetris.rotation_index = 0;
+        blocks.replace(active_tetris.index, active, tetris_children);
+
+        transform.translation = board
+            .spawn_position(blocks.piece(active_tetris.index))
+            .extend(0.0);
+        transform.rotation = Quat::default();
+
+        if let Some((ghost_tetris, ghost_tetris_children)) =
+            ghost_tetris_q.iter_many(field_children).next()
+        {
+            blocks.replace(
+                manager.current_tetris(),
+                ghost_tetris,
+                ghost_tetris_children,
+            );
+        }
 
-    let next_tetris_children = next_tetris_q.single_mut();
-    replace(manager.next_tetris(), next_tetris_children, &mut block_q);
+        for next_field_children in next_field_q.iter_many(field_children) {
+            for (next_tetris, next_tetris_children, next) in
+                next_tetris_q.iter_many(next_field_children)
+            {
+                blocks.replace(
+                    manager.next_tetris(next.slot),
+                    next_tetris,
+                    next_tetris_children,
+                );
+            }
+        }
+    }
 }
 
 pub fn check_advanced_block(
-    mut manager: ResMut<TetrisManager>,
-    mut game_state: ResMut<GameState>,
-    active_tetris_q: Query<(&Transform, &Children), With<ActiveTetris>>, // should be fine since there is no modification to the active tetris transform after transform propegation
+    mut commands: Commands,
+    mut board_q: Query<(&Board, &mut TetrisManager, &mut GameState, &Children)>,
+    active_tetris_q: Query<(Entity, &Transform, &Children), With<ActiveTetris>>, // should be fine since there is no modification to the active tetris transform after transform propegation
+    ghost_tetris_q: Query<Entity, With<GhostTetris>>,
+    children_q: Query<&Children, With<Board>>,
     block_q: Query<&Transform, With<Block>>,
     transform_q: Query<&Transform>,
 ) {
-    let (tetris_transform, children) = active_tetris_q.single();
-    if is_tetris_colliding(tetris_transform, children, &block_q, &transform_q) {
-        info!("Game over!");
-        *game_state = GameState::GameOver;
-    } else {
-        manager.fall_timer.reset();
-        manager.fast_fall_timer.reset();
-        manager.slide_timer.reset();
-        manager.slide_start_timer.reset();
-        manager.slide_dir = 0.0;
-        *game_state = GameState::Play;
+    for (board, mut manager, mut game_state, field_children) in board_q.iter_mut() {
+        if *game_state != GameState::Advance {
+            continue;
+        }
+        let Some((tetris, tetris_transform, children)) =
+            active_tetris_q.iter_many(field_children).next()
+        else {
+            continue;
+        };
+        commands.entity(tetris).insert(Visibility::Inherited);
+        if let Some(ghost_tetris) = ghost_tetris_q.iter_many(field_children).next() {
+            commands.entity(ghost_tetris).insert(Visibility::Inherited);
+        }
+
+        let placed_children = placed_children(board, field_children, &children_q);
+        let blocks = board_blocks(placed_children, &block_q);
+        if is_tetris_colliding(
+            tetris_transform,
+            children,
+            board.size,
+            &blocks,
+            &transform_q,
+        ) {
+            info!("Game over!");
+            *game_state = GameState::GameOver;
+        } else {
+            manager.reset_timers();
+            *game_state = GameState::Play;
+        }
     }
 }
 
 pub fn hold(
-    mut game_state: ResMut<GameState>,
-    mut manager: ResMut<TetrisManager>,
-    button_input: Res<ButtonInput<KeyCode>>,
-    ghost_tetris_q: Query<&Children, With<GhostTetris>>,
+    input: PlayerInput,
+    mut board_q: Query<(&Board, &mut TetrisManager, &mut GameState, &Children)>,
+    ghost_tetris_q: Query<(Entity, &Children), With<GhostTetris>>,
     mut active_tetris_q: Query<(&Children, &mut ActiveTetris, &mut Transform)>,
+    hold_field_q: Query<&Children, With<HoldField>>,
     mut hold_tetris_q: Query<(&Children, &mut Visibility, &mut HoldTetris)>,
-    mut block_q: Query<(&mut Transform, &mut Sprite), Without<ActiveTetris>>,
+    mut blocks: PieceBlocks,
 ) {
-    if manager.hold || !button_input.just_pressed(KeyCode::KeyC) {
-        return;
-    }
+    for (board, mut manager, mut game_state, field_children) in board_q.iter_mut() {
+        if *game_state != GameState::Play
+            || manager.hold
+            || !input.just_pressed(board, Action::Hold)
+        {
+            continue;
+        }
+        let Some(hold) = hold_field_q
+            .iter_many(field_children)
+            .next()
+            .and_then(|hold_field_children| board_child(hold_field_children, &hold_tetris_q))
+        else {
+            continue; // the rules don't allow holding
+        };
+        let Some(active) = board_child(field_children, &active_tetris_q) else {
+            continue;
+        };
+
+        let (hold_children, mut hold_vis, mut hold_tetris) = hold_tetris_q.get_mut(hold).unwrap();
+        let (active_children, mut active_tetris, mut active_transform) =
+            active_tetris_q.get_mut(active).unwrap();
+
+        blocks.replace(active_tetris.index, hold, hold_children);
+
+        if matches!(*hold_vis, Visibility::Visible) {
+            mem::swap(&mut active_tetris.index, &mut hold_tetris.index);
+            blocks.replace(active_tetris.index, active, active_children);
+            if let Some((ghost, ghost_children)) = ghost_tetris_q.iter_many(field_children).next() {
+                blocks.replace(active_tetris.index, ghost, ghost_children);
+            }
+            active_tetris.rotation_index = 0;
+            active_transform.rotation = Default::default();
+            active_transform.translation = board
+                .spawn_position(blocks.piece(active_tetris.index))
+                .extend(active_transform.translation.z);
+            manager.inputs = 0;
+            manager.rotated = false;
+        } else {
+            *hold_vis = Visibility::Visible;
+            hold_tetris.index = active_tetris.index;
+            *game_state = GameState::Advance;
+        }
 
-    let (active_children, mut active_tetris, mut active_transform) = active_tetris_q.single_mut();
-    let ghost_children = ghost_tetris_q.single();
-    let (hold_children, mut hold_vis, mut hold_tetris) = hold_tetris_q.single_mut();
-
-    replace(active_tetris.index, hold_children, &mut block_q);
-
-    if matches!(*hold_vis, Visibility::Visible) {
-        mem::swap(&mut active_tetris.index, &mut hold_tetris.index);
-        replace(active_tetris.index, active_children, &mut block_q);
-        replace(active_tetris.index, ghost_children, &mut block_q);
-        active_transform.rotation = Default::default();
-        active_transform.translation =
-            get_spawn_position(active_tetris.index).extend(active_transform.translation.z);
-    } else {
-        *hold_vis = Visibility::Visible;
-        hold_tetris.index = active_tetris.index;
-        *game_state = GameState::Advance;
+        manager.hold = true;
     }
-
-    manager.hold = true;
 }
 
 pub fn fall(
     time: Res<Time>,
-    button_input: Res<ButtonInput<KeyCode>>,
-    mut manager: ResMut<TetrisManager>,
-    mut game_state: ResMut<GameState>,
-    mut tetris_q: Query<&mut Transform, With<ActiveTetris>>,
-    children_q: Query<&Children, With<ActiveTetris>>,
-    block_q: Query<&Transform, (With<Block>, Without<ActiveTetris>)>,
-    transform_q: Query<&Transform, Without<ActiveTetris>>,
+    input: PlayerInput,
+    rules: Res<Rules>,
+    mut board_q: Query<(&Board, &mut TetrisManager, &mut GameState, &Children)>,
+    mut tetris_q: Query<(&mut Transform, &Children), With<ActiveTetris>>,
+    obstacles: Obstacles,
 ) {
-    let tetris_children = children_q.single();
-
-    let mut transform = tetris_q.single_mut();
-    let mut fall_transform = *transform;
-
-    if button_input.just_pressed(KeyCode::Space) {
-        while !is_tetris_colliding(&fall_transform, tetris_children, &block_q, &transform_q) {
-            transform.translation.y = fall_transform.translation.y;
-            fall_transform.translation.y -= BLOCK_SIZE.y;
+    for (board, mut manager, mut game_state, field_children) in board_q.iter_mut() {
+        if *game_state != GameState::Play {
+            continue;
+        }
+        let Some(active) = board_child(field_children, &tetris_q) else {
+            continue;
+        };
+        let placed = obstacles.placed(board, field_children);
+        let mut blocks = placed.clone();
+        if let Some(partner) = obstacles.partner(board, &tetris_q) {
+            let (partner_transform, partner_children) = tetris_q.get(partner).unwrap();
+            blocks.extend(obstacles.tetris_blocks(partner_transform, partner_children));
+        }
+        let (mut transform, tetris_children) = tetris_q.get_mut(active).unwrap();
+        let is_colliding = |transform: &Transform, blocks: &[Vec2]| {
+            obstacles.is_colliding(transform, tetris_children, board.size, blocks)
+        };
+        let mut fall_transform = *transform;
+
+        let step = board.cell_size().y;
+
+        if rules.hard_drop && input.just_pressed(board, Action::HardDrop) {
+            while !is_colliding(&fall_transform, &blocks) {
+                transform.translation.y = fall_transform.translation.y;
+                fall_transform.translation.y -= step;
+            }
+            *game_state = GameState::Place;
+            continue;
         }
-        *game_state = GameState::Place;
-        return;
-    }
 
-    let delta = time.delta();
-    let fast_fall = button_input.pressed(KeyCode::ArrowDown);
+        let delta = time.delta();
+        let fast_fall = input.pressed(board, Action::SoftDrop);
 
-    manager.fall_timer.tick(delta);
-    manager.fast_fall_timer.tick(delta);
+        manager.fall_timer.tick(delta);
+        manager.fast_fall_timer.tick(delta);
 
-    fall_transform.translation.y -= BLOCK_SIZE.y;
+        fall_transform.translation.y -= step;
 
-    if is_tetris_colliding(&fall_transform, tetris_children, &block_q, &transform_q) {
-        if !manager.hit_floor {
-            manager.hit_floor = true;
-            manager.fall_timer.reset(); // this allows player to slide and place a block
-        } else if manager.fall_timer.finished() {
-            *game_state = GameState::Place;
+        if is_colliding(&fall_transform, &blocks) {
+            if !is_colliding(&fall_transform, &placed) {
+                continue; // resting on the tetris of the co-op partner only waits for it to move
+            }
+            if !manager.hit_floor {
+                manager.hit_floor = true;
+                manager.lock_timer.reset(); // this allows player to slide and place a block
+            } else if manager.lock_timer.tick(delta).finished() {
+                *game_state = GameState::Place;
+            }
+            continue;
+        }
+        manager.hit_floor = false;
+
+        let rows = if manager.fall_rows >= GRID_HEIGHT {
+            board.play_size().y // 20G, the tetris never stays in the air
+        } else if fast_fall && manager.fast_fall_timer.finished()
+            || !fast_fall && manager.fall_timer.finished()
+        {
+            manager.fall_rows
+        } else {
+            0
+        };
+
+        for _ in 0..rows {
+            if is_colliding(&fall_transform, &blocks) {
+                break;
+            }
+            transform.translation.y = fall_transform.translation.y;
+            fall_transform.translation.y -= step;
+            manager.rotated = false;
         }
-        return;
-    }
-    manager.hit_floor = false;
-
-    if fast_fall && manager.fast_fall_timer.finished()
-        || !fast_fall && manager.fall_timer.finished()
-    {
-        transform.translation.y = fall_transform.translation.y;
     }
 }
 pub fn slide(
     time: Res<Time>,
-    button_input: Res<ButtonInput<KeyCode>>,
-    mut manager: ResMut<TetrisManager>,
-    mut tetris_q: Query<&mut Transform, With<ActiveTetris>>,
-    block_q: Query<&Transform, (With<Block>, Without<ActiveTetris>)>,
-    children_q: Query<&Children, With<ActiveTetris>>,
-    transform_q: Query<&Transform, Without<ActiveTetris>>,
+    input: PlayerInput,
+    mut board_q: Query<(&Board, &mut TetrisManager, &GameState, &Children)>,
+    mut tetris_q: Query<(&mut Transform, &Children), With<ActiveTetris>>,
+    obstacles: Obstacles,
 ) {
     let delta = time.delta();
 
-    if manager.slide_start_timer.tick(delta).finished() {
-        manager.slide_timer.tick(delta);
-    }
+    for (board, mut manager, game_state, field_children) in board_q.iter_mut() {
+        if *game_state != GameState::Play {
+            continue;
+        }
 
-    let mut direction = 0.0;
-    if button_input.pressed(KeyCode::ArrowLeft) {
-        direction -= 1.0;
-    }
-    if button_input.pressed(KeyCode::ArrowRight) {
-        direction += 1.0;
-    }
+        if manager.slide_start_timer.tick(delta).finished() {
+            manager.slide_timer.tick(delta);
+        }
 
-    if manager.slide_dir != direction {
-        manager.slide_dir = direction;
-        manager.slide_start_timer.reset();
-        manager.slide_timer.reset();
-    } else if !manager.slide_timer.finished() {
-        return;
-    }
+        let mut direction = 0.0;
+        if input.pressed(board, Action::Left) {
+            direction -= 1.0;
+        }
+        if input.pressed(board, Action::Right) {
+            direction += 1.0;
+        }
 
-    if direction == 0.0 {
-        return;
-    }
+        if manager.slide_dir != direction {
+            if direction != 0.0 {
+                manager.inputs += 1;
+            }
+            manager.slide_dir = direction;
+            manager.slide_start_timer.reset();
+            manager.slide_timer.reset();
+        } else if !manager.slide_timer.finished() {
+            continue;
+        }
 
-    let tetris_children = children_q.single();
-    let mut transform = tetris_q.single_mut();
-    let mut slid_transform = *transform;
-    slid_transform.translation.x += direction * BLOCK_SIZE.x;
+        if direction == 0.0 {
+            continue;
+        }
 
-    if is_tetris_colliding(&slid_transform, tetris_children, &block_q, &transform_q) {
-        return;
+        let Some(active) = board_child(field_children, &tetris_q) else {
+            continue;
+        };
+        let mut blocks = obstacles.placed(board, field_children);
+        if let Some(partner) = obstacles.partner(board, &tetris_q) {
+            let (partner_transform, partner_children) = tetris_q.get(partner).unwrap();
+            blocks.extend(obstacles.tetris_blocks(partner_transform, partner_children));
+        }
+        let (mut transform, tetris_children) = tetris_q.get_mut(active).unwrap();
+        let mut slid_transform = *transform;
+        slid_transform.translation.x += direction * board.cell_size().x;
+
+        if obstacles.is_colliding(&slid_transform, tetris_children, board.size, &blocks) {
+            continue;
+        }
+        transform.translation.x = slid_transform.translation.x;
+        manager.rotated = false;
     }
-    transform.translation.x = slid_transform.translation.x;
 }
 
 pub fn clear_block(
     mut commands: Commands,
-    mut game_state: ResMut<GameState>,
+    mode: Res<GameMode>,
+    mut board_q: Query<(
+        &Board,
+        &mut TetrisManager,
+        &mut Stats,
+        &mut Score,
+        &mut GameState,
+        &Children,
+    )>,
+    children_q: Query<&Children, With<Board>>,
     mut block_q: Query<(&mut Transform, Entity), With<Block>>,
-    mut score_q: Query<&mut Score>,
+    // both co-op players can place into the shared field at once, the blocks the first one
+    // cleared are only despawned after the system
+    mut cleared: Local<HashSet<Entity>>,
 ) {
-    let mut row_counter = HashMap::with_capacity(GRID_HEIGHT as usize);
-    for (transform, _) in block_q.iter() {
-        let yaxis = transform.translation.y.round() as i32;
-        *row_counter.entry(yaxis).or_insert(0) += 1;
-    }
+    cleared.clear();
+    for (board, mut manager, mut stats, mut score, mut game_state, field_children) in
+        board_q.iter_mut()
+    {
+        if *game_state != GameState::Place {
+            continue;
+        }
+        let field_children = placed_children(board, field_children, &children_q);
 
-    let mut full_rows = Vec::with_capacity(row_counter.len());
-    for (row, counter) in row_counter {
-        if counter >= GRID_WIDTH as usize {
-            full_rows.push(row);
+        let mut row_counter = HashMap::with_capacity(board.size.y as usize);
+        for (transform, entity) in block_q.iter_many(field_children) {
+            if cleared.contains(&entity) {
+                continue;
+            }
+            let yaxis = transform.translation.y.round() as i32;
+            *row_counter.entry(yaxis).or_insert(0) += 1;
+        }
+
+        // a full row clears every row of the cells it goes through, so big mode clears row pairs
+        let cell_blocks = board.cell_blocks;
+        let mut full_rows = HashSet::with_capacity(row_counter.len());
+        for (row, counter) in row_counter {
+            if counter >= board.size.x as usize {
+                let (_, index) = cell_index(board.size, vec2(0.0, row as f32));
+                let first = index as u32 / cell_blocks * cell_blocks;
+                full_rows.extend(
+                    (first..first + cell_blocks)
+                        .map(|index| cell_position(board.size, 0, index).y.round() as i32),
+                );
+            }
         }
-    }
 
-    let mut score = score_q.single_mut();
-    let len = full_rows.len();
+        // lines of cells, so a row pair is a single line in big mode
+        let len = full_rows.len() / cell_blocks as usize;
 
-    if len > 0 { // To avoid from change detection detecting when score is not changed, check if the length is more than 0
-        score.current_score += (len * len) as u64;
-        if score.current_score > score.best_score {
-            score.best_score = score.current_score;
+        stats.lines += len as u32;
+
+        if len > 0 {
+            // To avoid from change detection detecting when score is not changed, check if the length is more than 0
+            stats.clears[len.min(4) - 1] += 1;
+            score.current_score += mode.line_score(len as u32, stats.level);
+            if score.current_score > score.best_score {
+                score.best_score = score.current_score;
+            }
         }
-    }
 
-    for (mut transform, entity) in block_q.iter_mut() {
-        let yaxis = transform.translation.y.round() as i32;
-        for &row in &full_rows {
-            if yaxis == row {
-                commands.entity(entity).despawn();
-                break;
-            } else if yaxis > row {
-                transform.translation.y -= BLOCK_SIZE.y;
+        let mut blocks = block_q.iter_many_mut(field_children);
+        while let Some((mut transform, entity)) = blocks.fetch_next() {
+            if cleared.contains(&entity) {
+                continue;
+            }
+            let yaxis = transform.translation.y.round() as i32;
+            for &row in &full_rows {
+                if yaxis == row {
+                    commands.entity(entity).despawn();
+                    cleared.insert(entity);
+                    break;
+                } else if yaxis > row {
+                    transform.translation.y -= BLOCK_SIZE.y;
+                }
             }
         }
+
+        let mut delay = manager.spawn_delay;
+        if len > 0 {
+            delay += manager.line_clear_delay;
+        }
+
+        if delay.is_zero() {
+            *game_state = GameState::Advance;
+        } else {
+            manager.delay_timer.set_duration(delay);
+            manager.delay_timer.reset();
+            *game_state = GameState::Delay;
+        }
+    }
+}
+
+pub fn delay(time: Res<Time>, mut board_q: Query<(&mut TetrisManager, &mut GameState)>) {
+    for (mut manager, mut game_state) in board_q.iter_mut() {
+        if *game_state == GameState::Delay && manager.delay_timer.tick(time.delta()).finished() {
+            *game_state = GameState::Advance;
+        }
     }
+}
 
-    *game_state = GameState::Advance;
+// center of the cell at the given column and row, counted from the bottom left of the field
+pub fn cell_position(size: UVec2, column: u32, row: u32) -> Vec2 {
+    let grid_size = grid_size(size);
+    vec2(
+        (column as f32 + 0.5) * BLOCK_SIZE.x - grid_size.x * 0.5,
+        (row as f32 + 0.5) * BLOCK_SIZE.y - grid_size.y,
+    )
 }
 
-pub fn rotate(
-    button_input: Res<ButtonInput<KeyCode>>,
-    mut tetris_q: Query<(&mut Transform, &mut ActiveTetris)>,
-    block_q: Query<&Transform, (With<Block>, Without<ActiveTetris>)>,
-    children_q: Query<&Children, With<ActiveTetris>>,
-    transform_q: Query<&Transform, Without<ActiveTetris>>,
+// the opposite of cell_position, the cell may be outside the field
+pub fn cell_index(size: UVec2, point: Vec2) -> (i32, i32) {
+    let grid_size = grid_size(size);
+    let column = ((point.x + grid_size.x * 0.5) / BLOCK_SIZE.x).floor();
+    let row = ((point.y + grid_size.y) / BLOCK_SIZE.y).floor();
+    (column as i32, row as i32)
+}
+
+// None when the point is outside the field
+pub fn cell_at(size: UVec2, point: Vec2) -> Option<(u32, u32)> {
+    let (column, row) = cell_index(size, point);
+    if column < 0 || row < 0 || column >= size.x as i32 || row >= size.y as i32 {
+        return None;
+    }
+    Some((column as u32, row as u32))
+}
+
+// pushes every placed block of a board up and fills the space below with garbage rows,
+// each hole is the empty column of a row starting from the bottom one
+// NOTE: in big mode the rows and holes are in cells, so every row is 2 rows of blocks!
+pub fn insert_garbage(
+    commands: &mut Commands,
+    field: Entity,
+    field_children: &Children,
+    board: &Board,
+    sprite_handle: &Res<SpriteHandle>,
+    holes: &[u32],
+    block_q: &mut Query<&mut Transform, impl QueryFilter>,
 ) {
-    if !button_input.just_pressed(KeyCode::ArrowUp) {
-        return;
+    let height = holes.len() as f32 * board.cell_size().y;
+    let mut blocks = block_q.iter_many_mut(field_children);
+    while let Some(mut transform) = blocks.fetch_next() {
+        transform.translation.y += height;
     }
 
-    let (mut transform, mut active_tetris) = tetris_q.single_mut();
-    if active_tetris.index == tetris::O {
-        return; // no point in any rotation or rotation testing
+    let rect = Rect::from_corners(GARBAGE_RECT_START, GARBAGE_RECT_START + BLOCK_SIZE);
+    let cell_blocks = board.cell_blocks;
+    for row in 0..holes.len() as u32 * cell_blocks {
+        let hole = holes[(row / cell_blocks) as usize];
+        for column in (0..board.size.x).filter(|&column| column / cell_blocks != hole) {
+            let block = spawn_block(
+                commands,
+                field,
+                sprite_handle,
+                rect,
+                cell_position(board.size, column, row),
+            );
+            commands.entity(block).insert(Garbage);
+        }
     }
+}
 
-    let children = children_q.single();
+// spawns a placed block, the position is relative to the field space
+pub fn spawn_block(
+    commands: &mut Commands,
+    field: Entity,
+    sprite_handle: &Res<SpriteHandle>,
+    rect: Rect,
+    position: Vec2,
+) -> Entity {
+    commands
+        .spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    rect: Some(rect),
+                    // the parts of a big cell stretch a part of the sprite over the block
+                    custom_size: Some(BLOCK_SIZE),
+                    ..Default::default()
+                },
+                transform: Transform::from_translation(position.extend(0.0)),
+                texture: sprite_handle.0.clone(),
+                ..Default::default()
+            },
+            Block,
+        ))
+        .set_parent(field)
+        .id()
+}
+
+pub fn rotate(
+    input: PlayerInput,
+    rules: Res<Rules>,
+    mut board_q: Query<(&Board, &mut TetrisManager, &GameState, &Children)>,
+    mut tetris_q: Query<(&mut Transform, &mut ActiveTetris, &Children)>,
+    obstacles: Obstacles,
+) {
+    for (board, mut manager, game_state, field_children) in board_q.iter_mut() {
+        if *game_state != GameState::Play || !input.just_pressed(board, Action::Rotate) {
+            continue;
+        }
+        manager.inputs += 1;
+
+        let Some(active) = board_child(field_children, &tetris_q) else {
+            continue;
+        };
+        let mut blocks = obstacles.placed(board, field_children);
+        if let Some(partner) = obstacles.partner(board, &tetris_q) {
+            let (partner_transform, _, partner_children) = tetris_q.get(partner).unwrap();
+            blocks.extend(obstacles.tetris_blocks(partner_transform, partner_children));
+        }
+        let (mut transform, mut active_tetris, children) = tetris_q.get_mut(active).unwrap();
+        let piece = &rules.pieces[active_tetris.index];
+        if piece.fixed {
+            continue; // no point in any rotation or rotation testing
+        }
 
-    let mut rotated_transform = *transform;
-    rotated_transform.rotate_z(-90.0_f32.to_radians());
+        let flip_back = piece.orientations(&rules) == 2 && active_tetris.rotation_index % 2 == 1;
+        let angle: f32 = if flip_back { 90.0 } else { -90.0 };
 
-    let mut can_rotate = false;
+        let mut rotated_transform = *transform;
+        rotated_transform.rotate_z(angle.to_radians());
 
-    if !is_tetris_colliding(&rotated_transform, children, &block_q, &transform_q) {
-        can_rotate = true;
-    } else {
-        const TESTS: [f32; 4] = [1.0, -1.0, 2.0, -2.0]; // only x axis tests
-        let mut test_transform = rotated_transform;
+        let mut can_rotate = false;
 
-        for test in TESTS {
-            test_transform.translation.x = rotated_transform.translation.x + test * BLOCK_SIZE.x;
-            if !is_tetris_colliding(&test_transform, children, &block_q, &transform_q) {
-                rotated_transform.translation.x = test_transform.translation.x;
-                can_rotate = true;
-                break;
+        if !obstacles.is_colliding(&rotated_transform, children, board.size, &blocks) {
+            can_rotate = true;
+        } else if rules.kicks {
+            let mut test_transform = rotated_transform;
+
+            for &test in &piece.kicks {
+                test_transform.translation.x =
+                    rotated_transform.translation.x + test as f32 * board.cell_size().x;
+                if !obstacles.is_colliding(&test_transform, children, board.size, &blocks) {
+                    rotated_transform.translation.x = test_transform.translation.x;
+                    can_rotate = true;
+                    break;
+                }
             }
         }
-    }
 
-    if can_rotate {
-        transform.rotation = rotated_transform.rotation;
-        transform.translation = rotated_transform.translation; // because tests will change translation
-        active_tetris.rotation_index += 1;
-        active_tetris.rotation_index %= 4;
+        if can_rotate {
+            transform.rotation = rotated_transform.rotation;
+            transform.translation = rotated_transform.translation; // because tests will change translation
+            active_tetris.rotation_index += 1;
+            active_tetris.rotation_index %= 4;
+            manager.rotated = true;
+        }
     }
 }
 
 pub fn update_ghost(
+    board_q: Query<(&Board, &Children)>,
+    children_q: Query<&Children, With<Board>>,
     active_tetris_q: Query<
-        &Transform,
+        (&Transform, &Children),
         (With<ActiveTetris>, Without<GhostTetris>, Changed<Transform>),
     >,
     mut ghost_tetris_q: Query<&mut Transform, With<GhostTetris>>,
-    children_q: Query<&Children, With<ActiveTetris>>,
     block_q: Query<&Transform, (With<Block>, Without<GhostTetris>)>,
     transform_q: Query<&Transform, Without<GhostTetris>>,
 ) {
-    let Ok(tetris_transform) = active_tetris_q.get_single() else {
-        return;
-    };
-
-    let mut ghost_transform = ghost_tetris_q.single_mut();
-    let tetris_children = children_q.single();
-
-    *ghost_transform = *tetris_transform;
+    for (board, field_children) in board_q.iter() {
+        let Some((tetris_transform, tetris_children)) =
+            active_tetris_q.iter_many(field_children).next()
+        else {
+            continue;
+        };
+        let Some(ghost) = board_child(field_children, &ghost_tetris_q) else {
+            continue;
+        };
+        let mut ghost_transform = ghost_tetris_q.get_mut(ghost).unwrap();
+        // the ghost shows where the tetris lands on the field, the co-op partner moves on
+        let placed_children = placed_children(board, field_children, &children_q);
+        let blocks = board_blocks(placed_children, &block_q);
+
+        *ghost_transform = *tetris_transform;
+
+        while !is_tetris_colliding(
+            &ghost_transform,
+            tetris_children,
+            board.size,
+            &blocks,
+            &transform_q,
+        ) {
+            ghost_transform.translation.y -= board.cell_size().y;
+        }
 
-    while !is_tetris_colliding(&ghost_transform, tetris_children, &block_q, &transform_q) {
-        ghost_transform.translation.y -= BLOCK_SIZE.y;
+        ghost_transform.translation.y += board.cell_size().y;
+        ghost_transform.translation.z -= 1.0;
     }
-
-    ghost_transform.translation.y += BLOCK_SIZE.y;
-    ghost_transform.translation.z -= 1.0;
 }
@@ -0,0 +1,321 @@
+use bevy::math::*;
+use bevy::prelude::*;
+use bevy::sprite::*;
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use crate::attack::*;
+use crate::field::*;
+use crate::grid::*;
+use crate::mode::*;
+use crate::rules::*;
+use crate::tetris::*;
+use crate::*;
+
+const METER_WIDTH: f32 = 2.0;
+
+// rows an opponent sent, they can rise once the receiving board's clock reaches the ready time
+#[derive(Clone)]
+pub struct Incoming {
+    pub lines: u32,
+    pub ready: Duration,
+}
+
+// the garbage exchange of a board in a versus game
+#[derive(Component, Default, Clone)]
+pub struct Versus {
+    // clears in a row, 0 after a placement without one
+    pub combo: u32,
+    // tetrises and t-spins in a row, 0 after any other clear
+    pub back_to_back: u32,
+    // rows sent by the opponents that haven't risen yet, one entry per attack
+    pub incoming: VecDeque<Incoming>,
+    // the holes of the rows that rise before the next tetris spawns, from the bottom up
+    rising: Vec<u32>,
+}
+
+// garbage a board sends to every other board, an event of the game itself so that every
+// instance of an online game derives the same attacks from the same inputs
+#[derive(Event)]
+pub struct Attack {
+    pub from: Entity,
+    pub lines: u32,
+}
+
+#[derive(Component)]
+pub struct GarbageMeter;
+
+#[derive(Component)]
+pub struct VersusText;
+
+pub fn setup(mut commands: Commands, mode: Res<GameMode>, board_q: Query<(Entity, &Board)>) {
+    match *mode {
+        GameMode::Versus { attack } | GameMode::Royale { attack, .. } => {
+            commands.insert_resource(AttackTable::new(attack))
+        }
+        GameMode::Online => {} // the table comes from the host once connected
+        _ => return,
+    }
+
+    for (field, board) in board_q.iter() {
+        let grid_size = grid_size(board.size);
+        commands.entity(field).insert(Versus::default());
+        commands
+            .spawn((
+                SpriteBundle {
+                    sprite: Sprite {
+                        color: Color::rgb(1.0, 0.2, 0.2),
+                        custom_size: Some(vec2(METER_WIDTH, 0.0)),
+                        anchor: Anchor::BottomCenter,
+                        ..Default::default()
+                    },
+                    transform: Transform::from_translation(vec3(
+                        -grid_size.x * 0.5 - METER_WIDTH,
+                        -grid_size.y,
+                        1.0,
+                    )),
+                    ..Default::default()
+                },
+                GarbageMeter,
+            ))
+            .set_parent(field);
+    }
+}
+
+// runs right before the active tetris gets placed
+pub fn send(
+    rules: Res<Rules>,
+    table: Res<AttackTable>,
+    mut attacks: EventWriter<Attack>,
+    mut board_q: Query<(
+        Entity,
+        &Board,
+        &mut TetrisManager,
+        &Stats,
+        &GameState,
+        &Children,
+        &mut Versus,
+    )>,
+    active_tetris_q: Query<(&ActiveTetris, &Transform)>,
+    block_q: Query<&Transform, With<Block>>,
+) {
+    for (field, board, mut manager, stats, game_state, field_children, mut versus) in
+        board_q.iter_mut()
+    {
+        if *game_state != GameState::Place {
+            continue;
+        }
+        let Some((active_tetris, transform)) = active_tetris_q.iter_many(field_children).next()
+        else {
+            continue;
+        };
+        let spot = Spot::from_active(active_tetris, transform, board, &rules);
+        let mut grid = Grid::from_blocks(board, block_q.iter_many(field_children));
+        let t_spin = if manager.rotated {
+            attack::t_spin(&grid, &spot, &rules)
+        } else {
+            TSpin::None
+        };
+        let lines = grid.place(&spot, &rules);
+
+        if lines == 0 {
+            // the garbage that is ready rises once a placement clears nothing
+            versus.combo = 0;
+            let mut rows = 0;
+            while rows < table.cap {
+                let Some(incoming) = versus
+                    .incoming
+                    .front_mut()
+                    .filter(|incoming| incoming.ready <= stats.time)
+                else {
+                    break;
+                };
+                let lines = incoming.lines.min(table.cap - rows);
+                incoming.lines -= lines;
+                if incoming.lines == 0 {
+                    versus.incoming.pop_front();
+                }
+
+                let width = board.play_size().x;
+                let mut hole = manager.rng.u32(0..width);
+                for _ in 0..lines {
+                    if manager.rng.u32(0..100) < table.messiness {
+                        hole = (hole + manager.rng.u32(1..width)) % width;
+                    }
+                    versus.rising.push(hole);
+                }
+                rows += lines;
+            }
+            continue;
+        }
+
+        versus.combo += 1;
+        let clear = Clear {
+            lines,
+            t_spin,
+            combo: versus.combo,
+            back_to_back: versus.back_to_back,
+            perfect_clear: grid.rows.is_empty(),
+        };
+        let mut attack = table.attack(&clear);
+        if clear.is_difficult() {
+            versus.back_to_back += 1;
+        } else {
+            versus.back_to_back = 0;
+        }
+        if attack > 0 {
+            info!("Player {} attacks with {} lines", board.player + 1, attack);
+        }
+
+        // the attack cancels incoming garbage first
+        while attack > 0 {
+            let Some(incoming) = versus.incoming.front_mut() else {
+                break;
+            };
+            let cancelled = attack.min(incoming.lines);
+            incoming.lines -= cancelled;
+            attack -= cancelled;
+            if incoming.lines == 0 {
+                versus.incoming.pop_front();
+            }
+        }
+        if attack > 0 {
+            attacks.send(Attack {
+                from: field,
+                lines: attack,
+            });
+        }
+    }
+}
+
+pub fn deliver(
+    table: Res<AttackTable>,
+    mut attacks: EventReader<Attack>,
+    mut board_q: Query<(Entity, &Stats, &mut Versus)>,
+) {
+    for attack in attacks.read() {
+        for (field, stats, mut versus) in board_q.iter_mut() {
+            if field != attack.from {
+                versus.incoming.push_back(Incoming {
+                    lines: attack.lines,
+                    ready: stats.time + table.delay,
+                });
+            }
+        }
+    }
+}
+
+pub fn receive(
+    mut commands: Commands,
+    sprite_handle: Res<SpriteHandle>,
+    mut board_q: Query<(Entity, &Board, &mut GameState, &Children, &mut Versus)>,
+    mut block_q: Query<&mut Transform, With<Block>>,
+) {
+    for (field, board, mut game_state, field_children, mut versus) in board_q.iter_mut() {
+        if *game_state != GameState::Advance || versus.rising.is_empty() {
+            continue;
+        }
+        let holes = std::mem::take(&mut versus.rising);
+        insert_garbage(
+            &mut commands,
+            field,
+            field_children,
+            board,
+            &sprite_handle,
+            &holes,
+            &mut block_q,
+        );
+
+        if block_q
+            .iter_many(field_children)
+            .any(|transform| transform.translation.y > 0.0)
+        {
+            info!("Game over!");
+            *game_state = GameState::GameOver;
+        }
+    }
+}
+
+// the game ends for everyone once a board tops out
+pub fn finish(
+    mut commands: Commands,
+    font_handle: Res<FontHandle>,
+    mut board_q: Query<(Entity, &Board, &mut GameState), With<Versus>>,
+) {
+    if board_q
+        .iter()
+        .all(|(_, _, game_state)| matches!(*game_state, GameState::GameOver | GameState::Finish))
+    {
+        return; // already decided
+    }
+
+    for (field, board, mut game_state) in board_q.iter_mut() {
+        let result = if *game_state == GameState::GameOver {
+            "Lose"
+        } else {
+            *game_state = GameState::Finish;
+            "Win"
+        };
+        spawn_result(&mut commands, &font_handle, field, board.size, result);
+    }
+}
+
+// darkens the field of a board and writes how the game ended for it on top
+pub fn spawn_result(
+    commands: &mut Commands,
+    font_handle: &FontHandle,
+    field: Entity,
+    size: UVec2,
+    result: &str,
+) {
+    let grid_size = grid_size(size);
+    let text_style = TextStyle {
+        font: font_handle.0.clone(),
+        font_size: 200.0,
+        color: Color::WHITE,
+    };
+    commands
+        .spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color: Color::rgba(0.0, 0.0, 0.0, 0.75),
+                    custom_size: Some(grid_size),
+                    ..Default::default()
+                },
+                transform: Transform::from_translation(vec3(0.0, -grid_size.y * 0.5, 5.0)),
+                ..Default::default()
+            },
+            VersusText,
+        ))
+        .with_children(|builder| {
+            builder.spawn(Text2dBundle {
+                text: Text::from_section(result, text_style.clone()),
+                transform: Transform {
+                    translation: vec3(0.0, 0.0, 1.0),
+                    scale: Vec3::splat(12.0 / text_style.font_size),
+                    ..Default::default()
+                },
+                ..Default::default()
+            });
+        })
+        .set_parent(field);
+}
+
+pub fn update_meter(
+    board_q: Query<(&Board, &Versus, &Children), Changed<Versus>>,
+    mut meter_q: Query<&mut Sprite, With<GarbageMeter>>,
+) {
+    for (board, versus, field_children) in board_q.iter() {
+        let rows = versus
+            .incoming
+            .iter()
+            .map(|incoming| incoming.lines)
+            .sum::<u32>()
+            .min(board.play_size().y);
+        let mut meters = meter_q.iter_many_mut(field_children);
+        while let Some(mut sprite) = meters.fetch_next() {
+            sprite.custom_size = Some(vec2(METER_WIDTH, rows as f32 * board.cell_size().y));
+        }
+    }
+}